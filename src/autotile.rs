@@ -0,0 +1,224 @@
+///
+/// Picks tiles from their neighbours.
+/// Autotile rules live in tileset metadata, under `autotiles`. Each rule
+/// maps a bitmask of matching neighbours to a tile:
+///
+/// autotiles:
+///   ground:
+///     neighbours: 4
+///     default: 4
+///     tiles:
+///       14: 0   # right, down and left match, so this is a top edge
+///
+/// Bits are 1 up, 2 right, 4 down and 8 left. With 8 neighbours, corners add
+/// 16 up-right, 32 down-right, 64 down-left and 128 up-left. A corner only
+/// counts if both sides next to it match, so there are 47 distinct masks.
+///
+
+use std::collections::HashMap;
+use std::fmt;
+
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::level;
+
+pub const UP: u8 = 1;
+pub const RIGHT: u8 = 2;
+pub const DOWN: u8 = 4;
+pub const LEFT: u8 = 8;
+pub const UP_RIGHT: u8 = 16;
+pub const DOWN_RIGHT: u8 = 32;
+pub const DOWN_LEFT: u8 = 64;
+pub const UP_LEFT: u8 = 128;
+
+/// Reasons autotile rules can't be loaded.
+#[derive(Debug, PartialEq)]
+pub enum AutotileError {
+    /// The file couldn't be read or parsed.
+    Load(String),
+    /// A rule is malformed.
+    BadRule { name: String, msg: String }
+}
+
+impl fmt::Display for AutotileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AutotileError::Load(msg) => write!(f, "Could not load autotiles: {}", msg),
+            AutotileError::BadRule { name, msg } => write!(f, "Autotile \"{}\" is invalid: {}", name, msg)
+        }
+    }
+}
+
+/// Which neighbours a rule looks at.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Neighbourhood {
+    Four,
+    Eight
+}
+
+/// Maps neighbour masks to tiles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutotileRule {
+    pub neighbourhood: Neighbourhood,
+    /// Used for masks without a tile.
+    pub default: u32,
+    pub tiles: HashMap<u8, u32>
+}
+
+impl AutotileRule {
+    /// Picks the tile for a position.
+    /// `matches(dx, dy)` should return true if the neighbour at that offset connects.
+    pub fn tile<F: Fn(i32, i32) -> bool>(&self, matches: F) -> u32 {
+        let mask = neighbour_mask(self.neighbourhood, matches);
+        *self.tiles.get(&mask).unwrap_or(&self.default)
+    }
+}
+
+/// Returns the mask of connecting neighbours.
+/// y increases downwards.
+pub fn neighbour_mask<F: Fn(i32, i32) -> bool>(neighbourhood: Neighbourhood, matches: F) -> u8 {
+    let mut mask = 0;
+    for &(bit, dx, dy) in &[(UP, 0, -1), (RIGHT, 1, 0), (DOWN, 0, 1), (LEFT, -1, 0)] {
+        if matches(dx, dy) {
+            mask |= bit;
+        }
+    }
+
+    if neighbourhood == Neighbourhood::Eight {
+        // Corners only matter if both of their sides connect
+        for &(bit, sides, dx, dy) in &[(UP_RIGHT, UP | RIGHT, 1, -1), (DOWN_RIGHT, DOWN | RIGHT, 1, 1), (DOWN_LEFT, DOWN | LEFT, -1, 1), (UP_LEFT, UP | LEFT, -1, -1)] {
+            if mask & sides == sides && matches(dx, dy) {
+                mask |= bit;
+            }
+        }
+    }
+    mask
+}
+
+/// Autotile rules by name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutotileSet {
+    rules: HashMap<String, AutotileRule>
+}
+
+impl AutotileSet {
+    /// Creates a set with no rules.
+    pub fn empty() -> Self {
+        Self {
+            rules: HashMap::new()
+        }
+    }
+
+    /// Loads the rules from tileset metadata.
+    pub fn load(path: &str) -> Result<Self, AutotileError> {
+        let contents = std::fs::read_to_string(path).map_err(|err| AutotileError::Load(format!("{}: {}", path, err)))?;
+        Self::parse(&contents)
+    }
+
+    /// Parses the rules from tileset metadata.
+    /// Metadata without an `autotiles` section has no rules.
+    pub fn parse(contents: &str) -> Result<Self, AutotileError> {
+        let docs = YamlLoader::load_from_str(contents).map_err(|err| AutotileError::Load(err.to_string()))?;
+        let mut rules = HashMap::new();
+        let autotiles = match docs.first().map(|doc| &doc["autotiles"]) {
+            Some(Yaml::Hash(autotiles)) => autotiles,
+            Some(Yaml::BadValue) | None => return Ok(Self::empty()),
+            _ => return Err(AutotileError::Load(String::from("\"autotiles\" should map names to rules.")))
+        };
+        for (name, rule) in autotiles {
+            let name = name.as_str().ok_or_else(|| AutotileError::Load(String::from("Autotile names should be strings.")))?;
+            rules.insert(String::from(name), parse_rule(name, rule)?);
+        }
+
+        Ok(Self {
+            rules
+        })
+    }
+
+    /// Returns the rule with a name, if there is one.
+    pub fn rule(&self, name: &str) -> Option<&AutotileRule> {
+        self.rules.get(name)
+    }
+}
+
+/// Parses one rule.
+fn parse_rule(name: &str, rule: &Yaml) -> Result<AutotileRule, AutotileError> {
+    let neighbourhood = match rule["neighbours"].as_i64() {
+        Some(4) => Neighbourhood::Four,
+        Some(8) => Neighbourhood::Eight,
+        _ => return Err(bad_rule(name, "\"neighbours\" should be 4 or 8."))
+    };
+    let default = rule["default"].as_i64()
+        .ok_or_else(|| bad_rule(name, "\"default\" should be a tile index."))
+        .and_then(|tile| level::to_tile(tile).ok_or_else(|| bad_rule(name, &format!("{} is not a tile index.", tile))))?;
+
+    let mut tiles = HashMap::new();
+    if let Some(masks) = rule["tiles"].as_hash() {
+        for (mask, tile) in masks {
+            let mask = match mask.as_i64() {
+                Some(mask) if mask >= 0 && mask <= u8::MAX as i64 => mask as u8,
+                _ => return Err(bad_rule(name, "masks should be from 0 to 255."))
+            };
+            if neighbourhood == Neighbourhood::Four && mask > (UP | RIGHT | DOWN | LEFT) {
+                return Err(bad_rule(name, &format!("mask {} uses corners, but only 4 neighbours are checked.", mask)));
+            }
+            let tile = tile.as_i64()
+                .ok_or_else(|| bad_rule(name, &format!("mask {} should map to a tile index.", mask)))
+                .and_then(|tile| level::to_tile(tile).ok_or_else(|| bad_rule(name, &format!("{} is not a tile index.", tile))))?;
+            tiles.insert(mask, tile);
+        }
+    }
+
+    Ok(AutotileRule {
+        neighbourhood,
+        default,
+        tiles
+    })
+}
+
+/// Creates a BadRule error.
+fn bad_rule(name: &str, msg: &str) -> AutotileError {
+    AutotileError::BadRule {
+        name: String::from(name),
+        msg: String::from(msg)
+    }
+}
+
+/// Test if masks pick tiles, and corners need both sides.
+#[test]
+fn test_autotile_masks() {
+    let set = AutotileSet::parse("
+sprite_width: 16
+autotiles:
+  pipe:
+    neighbours: 4
+    default: 9
+    tiles:
+      5: 1
+      10: 2
+  ground:
+    neighbours: 8
+    default: 0
+    tiles:
+      255: 4
+").unwrap();
+
+    // A vertical pipe connects up and down
+    let pipe = set.rule("pipe").unwrap();
+    assert_eq!(pipe.tile(|dx, _| dx == 0), 1);
+    assert_eq!(pipe.tile(|_, dy| dy == 0), 2);
+    assert_eq!(pipe.tile(|_, _| false), 9);
+
+    // Corners without both sides are ignored
+    assert_eq!(neighbour_mask(Neighbourhood::Eight, |dx, dy| dy == 0 || (dx == 1 && dy == -1)), RIGHT | LEFT);
+    let ground = set.rule("ground").unwrap();
+    assert_eq!(ground.tile(|_, _| true), 4);
+    assert_eq!(ground.tile(|dx, dy| !(dx == 1 && dy == 1)), 0);
+
+    assert!(matches!(AutotileSet::parse("autotiles:\n  a:\n    neighbours: 4\n    default: 0\n    tiles:\n      16: 1\n"), Err(AutotileError::BadRule { .. })));
+
+    // Tiles must fit in a tile index
+    assert!(matches!(AutotileSet::parse("autotiles:\n  a:\n    neighbours: 4\n    default: 4294967296\n"), Err(AutotileError::BadRule { .. })));
+    assert!(matches!(AutotileSet::parse("autotiles:\n  a:\n    neighbours: 4\n    default: 0\n    tiles:\n      1: -1\n"), Err(AutotileError::BadRule { .. })));
+    assert!(matches!(AutotileSet::parse("autotiles:\n  a:\n    neighbours: 4\n    default: 4294967295\n"), Err(AutotileError::BadRule { .. })));
+}
@@ -0,0 +1,142 @@
+///
+/// A thread safe cell that checks borrows at runtime.
+/// Like RefCell, conflicting borrows panic instead of aliasing.
+///
+
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+/// Borrow flag value while mutably borrowed.
+const WRITING: isize = -1;
+
+pub struct BorrowCell<T> {
+    value: UnsafeCell<T>,
+    borrows: AtomicIsize
+}
+
+// The borrow flag guarantees either one writer or many readers
+unsafe impl<T: Send> Send for BorrowCell<T> {}
+unsafe impl<T: Send + Sync> Sync for BorrowCell<T> {}
+
+impl<T> BorrowCell<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            value: UnsafeCell::new(value),
+            borrows: AtomicIsize::new(0)
+        }
+    }
+
+    /// Borrows the value immutably.
+    /// Panics if the value is mutably borrowed.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        let mut borrows = self.borrows.load(Ordering::Acquire);
+        loop {
+            assert!(borrows != WRITING, "Value is already mutably borrowed.");
+            match self.borrows.compare_exchange_weak(borrows, borrows + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(curr_borrows) => borrows = curr_borrows
+            }
+        }
+
+        Ref {
+            value: unsafe { &*self.value.get() },
+            borrows: &self.borrows
+        }
+    }
+
+    /// Borrows the value mutably.
+    /// Panics if the value is already borrowed.
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        if self.borrows.compare_exchange(0, WRITING, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            panic!("Value is already borrowed.");
+        }
+
+        RefMut {
+            value: unsafe { &mut *self.value.get() },
+            borrows: &self.borrows
+        }
+    }
+
+    /// Consumes the cell and returns the value.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    /// Returns the value mutably.
+    /// No runtime check is needed, since we have exclusive access.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+/// An immutable borrow of a BorrowCell.
+pub struct Ref<'a, T: ?Sized> {
+    value: &'a T,
+    borrows: &'a AtomicIsize
+}
+
+impl<'a, T: ?Sized> Ref<'a, T> {
+    /// Maps the borrow to a part of the value.
+    pub fn map<U: ?Sized, F: FnOnce(&T) -> &U>(orig: Ref<'a, T>, f: F) -> Ref<'a, U> {
+        let orig = std::mem::ManuallyDrop::new(orig);
+        Ref {
+            value: f(orig.value),
+            borrows: orig.borrows
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for Ref<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> Drop for Ref<'a, T> {
+    fn drop(&mut self) {
+        self.borrows.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// A mutable borrow of a BorrowCell.
+pub struct RefMut<'a, T: ?Sized> {
+    value: &'a mut T,
+    borrows: &'a AtomicIsize
+}
+
+impl<'a, T: ?Sized> RefMut<'a, T> {
+    /// Maps the borrow to a part of the value.
+    pub fn map<U: ?Sized, F: FnOnce(&mut T) -> &mut U>(orig: RefMut<'a, T>, f: F) -> RefMut<'a, U> {
+        // Move the reference out without running Drop, which would release the borrow
+        let orig = std::mem::ManuallyDrop::new(orig);
+        let borrows = orig.borrows;
+        let value = unsafe { std::ptr::read(&orig.value) };
+        RefMut {
+            value: f(value),
+            borrows
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for RefMut<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for RefMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<'a, T: ?Sized> Drop for RefMut<'a, T> {
+    fn drop(&mut self) {
+        self.borrows.store(0, Ordering::Release);
+    }
+}
@@ -0,0 +1,98 @@
+///
+/// Camera that follows the player, like in Super Mario Bros.
+/// The player can move inside a dead zone without scrolling, the camera
+/// doesn't scroll back unless allowed, and it never shows past the level.
+///
+
+use crate::level::LevelBounds;
+
+/// Where the dead zone starts and ends, from the left of the screen.
+const DEAD_ZONE_LEFT: f32 = 64.0;
+const DEAD_ZONE_RIGHT: f32 = 112.0;
+
+/// The visible part of a level, in pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Camera {
+    pub x: f32,
+    /// Levels don't scroll vertically, so this only moves to stay inside the level.
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// The player can move between these, from the left of the screen, without scrolling.
+    pub dead_zone_left: f32,
+    pub dead_zone_right: f32,
+    /// Scrolls left when the player goes behind the dead zone.
+    pub scroll_back: bool
+}
+
+impl Camera {
+    /// Creates a camera at the top left of a level, which doesn't scroll back.
+    pub fn new(width: f32, height: f32) -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width,
+            height,
+            dead_zone_left: DEAD_ZONE_LEFT,
+            dead_zone_right: DEAD_ZONE_RIGHT,
+            scroll_back: false
+        }
+    }
+
+    /// Scrolls to keep a target's x inside the dead zone, then keeps the camera inside the level.
+    pub fn follow(&mut self, target_x: f32, bounds: &LevelBounds) {
+        if target_x > self.x + self.dead_zone_right {
+            self.x = target_x - self.dead_zone_right;
+        }
+        else if self.scroll_back && target_x < self.x + self.dead_zone_left {
+            self.x = target_x - self.dead_zone_left;
+        }
+        self.clamp(bounds);
+    }
+
+    /// Keeps the camera inside a level.
+    /// Levels smaller than the camera are shown from their top left.
+    pub fn clamp(&mut self, bounds: &LevelBounds) {
+        self.x = self.x.min(bounds.width - self.width).max(0.0);
+        self.y = self.y.min(bounds.height - self.height).max(0.0);
+    }
+}
+
+/// Test if the camera only scrolls outside the dead zone, and not back.
+#[test]
+fn test_camera_follow() {
+    let bounds = LevelBounds { width: 1000.0, height: 240.0, floor: 208.0 };
+    let mut camera = Camera::new(256.0, 240.0);
+
+    // Inside the dead zone
+    camera.follow(100.0, &bounds);
+    assert_eq!(camera.x, 0.0);
+
+    // Past the dead zone
+    camera.follow(300.0, &bounds);
+    assert_eq!(camera.x, 300.0 - DEAD_ZONE_RIGHT);
+
+    // Walking back doesn't scroll, unless allowed
+    camera.follow(200.0, &bounds);
+    assert_eq!(camera.x, 300.0 - DEAD_ZONE_RIGHT);
+    camera.scroll_back = true;
+    camera.follow(200.0, &bounds);
+    assert_eq!(camera.x, 200.0 - DEAD_ZONE_LEFT);
+
+    // The end of the level stops scrolling
+    camera.follow(990.0, &bounds);
+    assert_eq!(camera.x, 1000.0 - 256.0);
+}
+
+/// Test if the camera stays inside the level vertically too.
+#[test]
+fn test_camera_clamp() {
+    let mut camera = Camera::new(256.0, 240.0);
+    camera.y = 100.0;
+    camera.follow(0.0, &LevelBounds { width: 1000.0, height: 300.0, floor: 208.0 });
+    assert_eq!(camera.y, 300.0 - 240.0);
+
+    // Short levels are shown from their top
+    camera.follow(0.0, &LevelBounds { width: 1000.0, height: 160.0, floor: 128.0 });
+    assert_eq!(camera.y, 0.0);
+}
@@ -0,0 +1,209 @@
+///
+/// Buffers structural changes to the entity manager.
+/// Systems push commands while iterating, and the schedule applies them
+/// once each stage finishes.
+/// Each system fills its own buffer, so systems running in parallel never
+/// contend for one, and buffers are merged in system order.
+///
+
+use std::ops::{Deref, DerefMut};
+
+use crate::components::Component;
+use crate::entity_manager::{EntityId, EntityManager};
+
+type CommandFn = Box<dyn FnOnce(&mut EntityManager) + Send + Sync>;
+type SpawnFn = Box<dyn FnOnce(&mut EntityManager, EntityId) + Send + Sync>;
+
+pub struct Commands {
+    queue: Vec<CommandFn>
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self {
+            queue: Vec::new()
+        }
+    }
+
+    /// Queues a new entity.
+    /// Components can be chained onto the returned builder.
+    pub fn spawn(&mut self) -> SpawnCommands<'_> {
+        SpawnCommands {
+            commands: self,
+            comps: Vec::new()
+        }
+    }
+
+    /// Queues destroying an entity.
+    /// Skipped if the entity is already dead by then.
+    pub fn despawn(&mut self, entity_id: EntityId) {
+        self.push(move |entity_mgr| {
+            if entity_mgr.is_alive(entity_id) {
+                entity_mgr.destroy_entity(entity_id);
+            }
+        });
+    }
+
+    /// Queues adding a component to an entity.
+    /// Skipped if the entity is dead by then.
+    pub fn add<T: Component>(&mut self, entity_id: EntityId, comp: T) {
+        self.push(move |entity_mgr| {
+            if entity_mgr.is_alive(entity_id) {
+                entity_mgr.insert(entity_id, comp);
+            }
+        });
+    }
+
+    /// Queues removing a component from an entity.
+    /// Skipped if the entity is dead by then.
+    pub fn remove<T: Component>(&mut self, entity_id: EntityId) {
+        self.push(move |entity_mgr| {
+            if entity_mgr.is_alive(entity_id) {
+                entity_mgr.remove::<T>(entity_id);
+            }
+        });
+    }
+
+    /// Queues an arbitrary change.
+    pub fn push<F: FnOnce(&mut EntityManager) + Send + Sync + 'static>(&mut self, command: F) {
+        self.queue.push(Box::new(command));
+    }
+
+    /// Moves another buffer's commands to the end of this one.
+    pub fn append(&mut self, mut other: Commands) {
+        self.queue.append(&mut other.queue);
+    }
+
+    /// Returns true if no commands are queued.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Runs all queued commands in the order they were pushed.
+    pub fn apply(self, entity_mgr: &mut EntityManager) {
+        for command in self.queue {
+            command(entity_mgr);
+        }
+    }
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Commands being queued by the running system.
+/// When dropped, they join the system's other commands, or the shared queue outside of systems.
+pub struct SystemCommands<'a> {
+    commands: Commands,
+    entity_mgr: &'a EntityManager
+}
+
+impl<'a> SystemCommands<'a> {
+    pub fn new(entity_mgr: &'a EntityManager) -> Self {
+        Self {
+            commands: Commands::new(),
+            entity_mgr
+        }
+    }
+}
+
+impl<'a> Deref for SystemCommands<'a> {
+    type Target = Commands;
+
+    fn deref(&self) -> &Commands {
+        &self.commands
+    }
+}
+
+impl<'a> DerefMut for SystemCommands<'a> {
+    fn deref_mut(&mut self) -> &mut Commands {
+        &mut self.commands
+    }
+}
+
+impl<'a> Drop for SystemCommands<'a> {
+    fn drop(&mut self) {
+        self.entity_mgr.queue_thread_commands(std::mem::take(&mut self.commands));
+    }
+}
+
+/// Builds up the components of a queued entity.
+pub struct SpawnCommands<'a> {
+    commands: &'a mut Commands,
+    comps: Vec<SpawnFn>
+}
+
+impl<'a> SpawnCommands<'a> {
+    /// Adds a component to the new entity.
+    pub fn with<T: Component>(mut self, comp: T) -> Self {
+        self.comps.push(Box::new(move |entity_mgr, entity_id| {
+            entity_mgr.insert(entity_id, comp);
+        }));
+        self
+    }
+}
+
+impl<'a> Drop for SpawnCommands<'a> {
+    fn drop(&mut self) {
+        // Queue the entity once the builder is finished
+        let comps = std::mem::take(&mut self.comps);
+        self.commands.push(move |entity_mgr| {
+            let entity_id = entity_mgr.create_entity();
+            for add_comp in comps {
+                add_comp(entity_mgr, entity_id);
+            }
+        });
+    }
+}
+
+/// Test if commands are deferred until applied.
+#[test]
+fn test_commands() {
+    use crate::components::PositionComponent;
+
+    let mut entity_mgr = EntityManager::new();
+    let entity_id = entity_mgr.create_entity();
+    entity_mgr.add::<PositionComponent>(entity_id);
+
+    // Spawn a new entity for every existing one while iterating
+    {
+        let mut commands = entity_mgr.commands();
+        let mut query = entity_mgr.query::<(EntityId, &PositionComponent)>();
+        for (entity_id, pos_comp) in query.iter() {
+            let mut new_pos_comp = PositionComponent::uninit();
+            new_pos_comp.x = pos_comp.x + 16;
+            commands.spawn().with(new_pos_comp);
+            commands.despawn(entity_id);
+        }
+    }
+    assert_eq!(entity_mgr.query::<&PositionComponent>().iter().count(), 1);
+
+    entity_mgr.apply_commands();
+    let mut query = entity_mgr.query::<&PositionComponent>();
+    let xs: Vec<i32> = query.iter().map(|pos_comp| pos_comp.x).collect();
+    assert_eq!(xs, vec![16]);
+    assert!(!entity_mgr.is_alive(entity_id));
+}
+
+/// Test if commands queued from another thread, outside of any system, are still applied.
+#[test]
+fn test_commands_other_thread() {
+    use crate::components::PositionComponent;
+
+    let mut entity_mgr = EntityManager::new();
+    entity_mgr.add_event::<u32>();
+    std::thread::scope(|scope| {
+        let entity_mgr = &entity_mgr;
+        scope.spawn(move || {
+            entity_mgr.commands().spawn().with(PositionComponent::uninit());
+            entity_mgr.event_writer::<u32>().send(7);
+        });
+    });
+
+    entity_mgr.apply_commands();
+    assert_eq!(entity_mgr.query::<&PositionComponent>().iter().count(), 1);
+    let mut reader = crate::events::EventReader::<u32>::new();
+    assert_eq!(reader.iter(&entity_mgr.res::<crate::events::Events<u32>>()).collect::<Vec<_>>(), vec![&7]);
+}
@@ -0,0 +1,249 @@
+///
+/// Stores all components of a single type.
+///
+
+use std::any::Any;
+
+use crate::components::Component;
+use crate::entity_manager::EntityId;
+
+const INITIAL_COMPS_LEN: usize = 32;
+
+/// Type erased view of a component list.
+/// Lets the entity manager hold lists of any component type.
+pub trait AnyComponentList: Send + Sync {
+    /// Frees the entity's component, if it has one.
+    /// The removal is recorded at the given tick.
+    fn remove_entity(&mut self, entity_id: EntityId, tick: u32);
+
+    /// Forgets removals recorded at or before a tick.
+    fn clear_removed(&mut self, tick: u32);
+
+    /// Moves the removals recorded by another list of the same type into this one.
+    fn append_removed(&mut self, other: &mut dyn AnyComponentList);
+
+    /// Returns the list as `Any`, for downcasting.
+    fn as_any(&self) -> &dyn Any;
+
+    /// Returns the list as `Any` mutably, for downcasting.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// When a component was added and last changed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ComponentTicks {
+    pub added: u32,
+    pub changed: u32
+}
+
+impl ComponentTicks {
+    fn new(tick: u32) -> Self {
+        Self {
+            added: tick,
+            changed: tick
+        }
+    }
+
+    /// Returns true if the component was added after a tick.
+    pub fn is_added(&self, last_tick: u32) -> bool {
+        self.added > last_tick
+    }
+
+    /// Returns true if the component was added or changed after a tick.
+    pub fn is_changed(&self, last_tick: u32) -> bool {
+        self.changed > last_tick
+    }
+}
+
+/// Marks an entity with no component in the sparse list.
+const EMPTY: u32 = u32::MAX;
+
+/// A sparse set of components.
+/// Components are packed densely for iteration, and the sparse list
+/// maps entity indices to their place in the dense lists.
+pub struct ComponentList<T: Component> {
+    comps: Vec<T>,
+    ticks: Vec<ComponentTicks>,
+    entity_inds: Vec<u32>,
+    sparse: Vec<u32>,
+    removed: Vec<(EntityId, u32)>
+}
+
+impl<T: Component> ComponentList<T> {
+    pub fn new() -> Self {
+        Self {
+            comps: Vec::with_capacity(INITIAL_COMPS_LEN),
+            ticks: Vec::with_capacity(INITIAL_COMPS_LEN),
+            entity_inds: Vec::with_capacity(INITIAL_COMPS_LEN),
+            sparse: Vec::new(),
+            removed: Vec::new()
+        }
+    }
+
+    /// Adds a component to the entity.
+    /// If the entity already has one, it is reset.
+    pub fn add(&mut self, entity_index: u32, tick: u32) {
+        self.insert(entity_index, T::uninit(), tick);
+    }
+
+    /// Adds an initialized component to the entity.
+    /// If the entity already has one, it is replaced and counts as changed.
+    pub fn insert(&mut self, entity_index: u32, comp: T, tick: u32) {
+        match self.dense_index(entity_index) {
+            Some(ind) => {
+                self.comps[ind] = comp;
+                self.ticks[ind].changed = tick;
+            },
+            None => {
+                let sparse_index = entity_index as usize;
+                if sparse_index >= self.sparse.len() {
+                    self.sparse.resize(sparse_index + 1, EMPTY);
+                }
+                self.sparse[sparse_index] = self.comps.len() as u32;
+                self.comps.push(comp);
+                self.ticks.push(ComponentTicks::new(tick));
+                self.entity_inds.push(entity_index);
+            }
+        }
+    }
+
+    /// Removes the entity's component, if it has one.
+    /// Returns true if there was one to remove.
+    pub fn remove(&mut self, entity_index: u32) -> bool {
+        let ind = match self.dense_index(entity_index) {
+            Some(ind) => ind,
+            None => return false
+        };
+
+        // Move the last component into the gap
+        self.comps.swap_remove(ind);
+        self.ticks.swap_remove(ind);
+        self.entity_inds.swap_remove(ind);
+        if let Some(&moved_index) = self.entity_inds.get(ind) {
+            self.sparse[moved_index as usize] = ind as u32;
+        }
+        self.sparse[entity_index as usize] = EMPTY;
+        true
+    }
+
+    /// Returns true if the entity has a component in this list.
+    pub fn has(&self, entity_index: u32) -> bool {
+        self.dense_index(entity_index).is_some()
+    }
+
+    /// Returns the entity's component, if it has one.
+    pub fn get(&self, entity_index: u32) -> Option<&T> {
+        self.dense_index(entity_index).map(|ind| &self.comps[ind])
+    }
+
+    /// Returns the entity's component mutably, marking it changed.
+    pub fn get_mut(&mut self, entity_index: u32, tick: u32) -> Option<&mut T> {
+        let ind = self.dense_index(entity_index)?;
+        self.ticks[ind].changed = tick;
+        Some(&mut self.comps[ind])
+    }
+
+    /// Returns when the entity's component was added and changed.
+    pub fn ticks(&self, entity_index: u32) -> Option<ComponentTicks> {
+        self.dense_index(entity_index).map(|ind| self.ticks[ind])
+    }
+
+    /// Iterates over entities whose component was removed after a tick.
+    pub fn removed(&self, last_tick: u32) -> impl Iterator<Item = EntityId> + '_ {
+        self.removed.iter()
+            .filter(move |(_, tick)| *tick > last_tick)
+            .map(|(entity_id, _)| *entity_id)
+    }
+
+    /// Iterates over entity indices and their components.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &T)> {
+        self.entity_inds.iter().copied().zip(self.comps.iter())
+    }
+
+    /// Returns the number of components.
+    pub fn len(&self) -> usize {
+        self.comps.len()
+    }
+
+    /// Returns true if there are no components.
+    pub fn is_empty(&self) -> bool {
+        self.comps.is_empty()
+    }
+
+    /// Returns the entity indices of every component, in dense order.
+    pub fn entity_indices(&self) -> &[u32] {
+        &self.entity_inds
+    }
+
+    /// Returns a pointer to the start of the component list.
+    /// Used by queries to hand out several mutable components at once.
+    pub fn comps_ptr(&mut self) -> *mut T {
+        self.comps.as_mut_ptr()
+    }
+
+    /// Returns a pointer to the start of the tick list.
+    /// Used by queries to mark components changed.
+    pub fn ticks_ptr(&mut self) -> *mut ComponentTicks {
+        self.ticks.as_mut_ptr()
+    }
+
+    /// Returns where the entity's component is in the dense lists.
+    pub fn dense_index(&self, entity_index: u32) -> Option<usize> {
+        match self.sparse.get(entity_index as usize) {
+            Some(&ind) if ind != EMPTY => Some(ind as usize),
+            _ => None
+        }
+    }
+}
+
+impl<T: Component> Default for ComponentList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Component> AnyComponentList for ComponentList<T> {
+    fn remove_entity(&mut self, entity_id: EntityId, tick: u32) {
+        if self.remove(entity_id.index) {
+            self.removed.push((entity_id, tick));
+        }
+    }
+
+    fn clear_removed(&mut self, tick: u32) {
+        self.removed.retain(|&(_, removed_tick)| removed_tick > tick);
+    }
+
+    fn append_removed(&mut self, other: &mut dyn AnyComponentList) {
+        let other = other.as_any_mut().downcast_mut::<ComponentList<T>>().unwrap();
+        self.removed.append(&mut other.removed);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Test if removing components keeps the dense lists packed.
+#[test]
+fn test_component_list_remove() {
+    let mut list = ComponentList::<crate::components::PositionComponent>::new();
+    for entity_index in 0..4 {
+        list.add(entity_index, 0);
+        list.get_mut(entity_index, 0).unwrap().x = entity_index as i32;
+    }
+    assert!(list.remove(1));
+    assert!(!list.remove(1));
+    assert!(!list.has(1));
+    assert!(list.get(1).is_none());
+    assert_eq!(list.len(), 3);
+
+    // The last component was moved into the gap
+    assert_eq!(list.entity_indices(), &[0, 3, 2]);
+    assert_eq!(list.get(3).unwrap().x, 3);
+    let xs: Vec<i32> = list.iter().map(|(_, pos_comp)| pos_comp.x).collect();
+    assert_eq!(xs, vec![0, 3, 2]);
+}
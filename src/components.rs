@@ -4,5 +4,11 @@ pub mod sprite_component;
 pub use sprite_component::SpriteComponent;
 pub mod position_component;
 pub use position_component::PositionComponent;
+pub mod player_component;
+pub use player_component::PlayerComponent;
+pub mod parent_component;
+pub use parent_component::ParentComponent;
+pub mod children_component;
+pub use children_component::ChildrenComponent;
 pub mod component;
 pub use component::Component;
\ No newline at end of file
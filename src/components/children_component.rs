@@ -0,0 +1,32 @@
+///
+/// Component that lists the entities attached to this one.
+/// Kept in sync by the entity manager's set_parent.
+/// 
+
+use super::Component;
+use crate::entity_manager::EntityId;
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+pub struct ChildrenComponent {
+    pub children: Vec<EntityId>
+}
+
+impl Component for ChildrenComponent {
+    fn uninit() -> Self {
+        Self {
+            children: Vec::new()
+        }
+    }
+}
+
+impl Snapshot for ChildrenComponent {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.children);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Self {
+            children: reader.read()?
+        })
+    }
+}
\ No newline at end of file
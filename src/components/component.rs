@@ -2,7 +2,7 @@
 /// Trait for components.
 /// 
 
-pub trait Component {
+pub trait Component: Send + Sync + 'static {
     /// Returns an uninitialized instance of this component.
     fn uninit() -> Self;
 }
\ No newline at end of file
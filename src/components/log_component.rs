@@ -2,11 +2,13 @@
 /// A component that logs info.
 /// 
 
+use yaml_rust::Yaml;
+
 use super::Component;
+use crate::prefab::{self, FromPrefab, PrefabError};
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
 
 pub struct LogComponent {
-    pub exists: bool,
-    pub id: u32,
     pub has_info: bool,
     pub message: String
 }
@@ -14,10 +16,34 @@ pub struct LogComponent {
 impl Component for LogComponent {
     fn uninit() -> Self {
         Self {
-            exists: false,
-            id: 0,
             has_info: false,
             message: String::from("")
         }
     }
+}
+
+impl Snapshot for LogComponent {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.has_info);
+        writer.write(&self.message);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Self {
+            has_info: reader.read()?,
+            message: reader.read()?
+        })
+    }
+}
+
+impl FromPrefab for LogComponent {
+    const FIELDS: &'static [&'static str] = &["message"];
+
+    fn apply_fields(&mut self, fields: &Yaml) -> Result<(), PrefabError> {
+        if let Some(message) = prefab::read_string(fields, "message")? {
+            self.has_info = true;
+            self.message = message;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
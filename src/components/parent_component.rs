@@ -0,0 +1,40 @@
+///
+/// Component that attaches an entity to a parent.
+/// The entity's position is kept at an offset from the parent's.
+/// 
+
+use super::Component;
+use crate::entity_manager::EntityId;
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+pub struct ParentComponent {
+    pub parent: EntityId,
+    pub offset_x: i32,
+    pub offset_y: i32
+}
+
+impl Component for ParentComponent {
+    fn uninit() -> Self {
+        Self {
+            parent: EntityId::NULL,
+            offset_x: 0,
+            offset_y: 0
+        }
+    }
+}
+
+impl Snapshot for ParentComponent {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.parent);
+        writer.write(&self.offset_x);
+        writer.write(&self.offset_y);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Self {
+            parent: reader.read()?,
+            offset_x: reader.read()?,
+            offset_y: reader.read()?
+        })
+    }
+}
\ No newline at end of file
@@ -0,0 +1,33 @@
+///
+/// Tag component that marks an entity as the player.
+/// 
+
+use yaml_rust::Yaml;
+
+use super::Component;
+use crate::prefab::{FromPrefab, PrefabError};
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+pub struct PlayerComponent;
+
+impl Component for PlayerComponent {
+    fn uninit() -> Self {
+        Self
+    }
+}
+
+impl Snapshot for PlayerComponent {
+    fn save(&self, _writer: &mut SnapshotWriter) {}
+
+    fn load(_reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Self)
+    }
+}
+
+impl FromPrefab for PlayerComponent {
+    const FIELDS: &'static [&'static str] = &[];
+
+    fn apply_fields(&mut self, _fields: &Yaml) -> Result<(), PrefabError> {
+        Ok(())
+    }
+}
\ No newline at end of file
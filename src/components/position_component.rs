@@ -2,11 +2,13 @@
 /// Component that represents a position.
 /// 
 
+use yaml_rust::Yaml;
+
 use super::Component;
+use crate::prefab::{self, FromPrefab, PrefabError};
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
 
 pub struct PositionComponent {
-    pub exists: bool,
-    pub id: u32,
     pub x: i32,
     pub y: i32,
     pub prec_x: f32,
@@ -18,8 +20,6 @@ pub struct PositionComponent {
 impl Component for PositionComponent {
     fn uninit() -> Self {
         Self {
-            exists: false,
-            id: 0,
             x: 0,
             y: 0,
             prec_x: 0.0,
@@ -28,4 +28,48 @@ impl Component for PositionComponent {
             spd_y: 0.0
         }
     }
+}
+
+impl Snapshot for PositionComponent {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.x);
+        writer.write(&self.y);
+        writer.write(&self.prec_x);
+        writer.write(&self.prec_y);
+        writer.write(&self.spd_x);
+        writer.write(&self.spd_y);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Self {
+            x: reader.read()?,
+            y: reader.read()?,
+            prec_x: reader.read()?,
+            prec_y: reader.read()?,
+            spd_x: reader.read()?,
+            spd_y: reader.read()?
+        })
+    }
+}
+
+impl FromPrefab for PositionComponent {
+    const FIELDS: &'static [&'static str] = &["x", "y", "spd_x", "spd_y"];
+
+    fn apply_fields(&mut self, fields: &Yaml) -> Result<(), PrefabError> {
+        if let Some(x) = prefab::read_int(fields, "x")? {
+            self.x = x as i32;
+            self.prec_x = x as f32;
+        }
+        if let Some(y) = prefab::read_int(fields, "y")? {
+            self.y = y as i32;
+            self.prec_y = y as f32;
+        }
+        if let Some(spd_x) = prefab::read_float(fields, "spd_x")? {
+            self.spd_x = spd_x;
+        }
+        if let Some(spd_y) = prefab::read_float(fields, "spd_y")? {
+            self.spd_y = spd_y;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -2,32 +2,105 @@
 /// Component that represents a sprite to be drawn.
 /// 
 
+use yaml_rust::Yaml;
+
 use super::Component;
+use crate::level::{self, TILE_SIZE};
+use crate::prefab::{self, FromPrefab, PrefabError};
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
 
 pub struct SpriteComponent {
-    pub exists: bool,
-    pub id: u32,
     pub quad_id: u32,
     pub tex_name: String,
     pub sprite_index: u32,
     pub tilemap: Option<Vec<u32>>,
     pub tilemap_width: u32,
     pub tilemap_height: u32,
-    pub should_update: bool,
+    /// Size a tilemap's tiles are drawn at, in pixels, which should match its level's grid.
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// Sprites with higher orders are drawn in front.
+    pub draw_order: i32
 }
 
 impl Component for SpriteComponent {
     fn uninit() -> Self {
         Self {
-            exists: false,
-            id: 0,
             quad_id: 0,
             tex_name: String::from("black"),
             sprite_index: 0,
             tilemap: None,
             tilemap_width: 0,
             tilemap_height: 0,
-            should_update: true
+            tile_width: TILE_SIZE,
+            tile_height: TILE_SIZE,
+            draw_order: 0
+        }
+    }
+}
+
+impl SpriteComponent {
+    /// Changes one tile of a tilemap. The draw system only rebuilds the chunk holding it.
+    /// Returns false if the sprite isn't a tilemap, (x, y) is outside it, or it doesn't match its size.
+    pub fn set_tile(&mut self, x: u32, y: u32, tile: u32) -> bool {
+        let (width, height) = (self.tilemap_width, self.tilemap_height);
+        match &mut self.tilemap {
+            Some(tilemap) if x < width && y < height && level::tilemap_fits(tilemap, width, height) => {
+                tilemap[(y * width + x) as usize] = tile;
+                true
+            },
+            _ => false
+        }
+    }
+}
+
+impl Snapshot for SpriteComponent {
+    // The quad belongs to the renderer, so loaded sprites get a new one
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.tex_name);
+        writer.write(&self.sprite_index);
+        writer.write(&self.tilemap);
+        writer.write(&self.tilemap_width);
+        writer.write(&self.tilemap_height);
+        writer.write(&self.tile_width);
+        writer.write(&self.tile_height);
+        writer.write(&self.draw_order);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        let sprite_comp = Self {
+            quad_id: 0,
+            tex_name: reader.read()?,
+            sprite_index: reader.read()?,
+            tilemap: reader.read()?,
+            tilemap_width: reader.read()?,
+            tilemap_height: reader.read()?,
+            tile_width: reader.read()?,
+            tile_height: reader.read()?,
+            draw_order: reader.read()?
+        };
+        match &sprite_comp.tilemap {
+            Some(tilemap) if !level::tilemap_fits(tilemap, sprite_comp.tilemap_width, sprite_comp.tilemap_height) => {
+                Err(SnapshotError::InvalidData(format!("Tilemap has {} tiles, but is {}x{}.", tilemap.len(), sprite_comp.tilemap_width, sprite_comp.tilemap_height)))
+            },
+            _ => Ok(sprite_comp)
+        }
+    }
+}
+
+impl FromPrefab for SpriteComponent {
+    const FIELDS: &'static [&'static str] = &["tex_name", "sprite_index", "draw_order"];
+
+    fn apply_fields(&mut self, fields: &Yaml) -> Result<(), PrefabError> {
+        if let Some(tex_name) = prefab::read_string(fields, "tex_name")? {
+            self.tex_name = tex_name;
+        }
+        if let Some(sprite_index) = prefab::read_int(fields, "sprite_index")? {
+            self.sprite_index = sprite_index as u32;
+        }
+        if let Some(draw_order) = prefab::read_int(fields, "draw_order")? {
+            self.draw_order = draw_order as i32;
         }
+        Ok(())
     }
 }
\ No newline at end of file
@@ -2,162 +2,980 @@
 /// Manages game entities.
 /// 
 
+use std::any::{self, Any, TypeId};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
 use std::vec;
-use crate::components::{PositionComponent, SpriteComponent};
-
-use super::components::LogComponent;
-use super::components::Component;
-
-/// Automates adding a new component to the manager.
-#[macro_export]
-macro_rules! setup_comp {
-    ($comp_type:ident, $entity_comps_name:ident, $comp_ind_name:ident, $add_name:ident, $get_name:ident, $get_name_immut:ident, $index_name:ident) => {
-        /// Adds a component to the entity.
-        pub fn $add_name(&mut self, entity_id: u32) {
-            let ind = self.$index_name();
-            self.$entity_comps_name[ind as usize].exists = true;
-            self.entities[entity_id as usize].$comp_ind_name = ind;
-        }
-
-        /// Returns an entity's component.
-        pub fn $get_name(&mut self, entity_id: u32) -> &mut $comp_type {
-            let ind = self.entities[entity_id as usize].$comp_ind_name;
-            return &mut self.$entity_comps_name[ind as usize];
-        }
-
-        /// Returns an entity's component immutably.
-        pub fn $get_name_immut(&self, entity_id: u32) -> &$comp_type {
-            let ind = self.entities[entity_id as usize].$comp_ind_name;
-            return &self.$entity_comps_name[ind as usize];
-        }
-        
-        /// Returns the next free index in the component list.
-        /// May expand component list.
-        fn $index_name(&mut self) -> u32 {
-            match self.$entity_comps_name.iter().find(|&comp| {!comp.exists}) {
-                Some(comp) => comp.id,
-                None => {
-                    self.$entity_comps_name.push($comp_type::uninit());
-                    self.$entity_comps_name.len() as u32 - 1
-                },
-            }
-        }
-    };
-}
+
+use crate::borrow_cell::{BorrowCell, Ref, RefMut};
+use crate::commands::{Commands, SystemCommands};
+use crate::component_list::{AnyComponentList, ComponentList};
+use crate::events::{EventWriter, Events};
+use crate::prefab::{self, FromPrefab, PrefabError, PrefabLibrary};
+use crate::query::{Query, QueryBorrow};
+
+use crate::snapshot::{Snapshot, SnapshotError, SnapshotReader, SnapshotWriter};
+
+use yaml_rust::Yaml;
+
+use super::components::{ChildrenComponent, Component, LogComponent, ParentComponent, PlayerComponent, PositionComponent, SpriteComponent};
 
 pub struct EntityManager {
     pub entities: Vec<Entity>,
-    log_comps: Vec<LogComponent>,
-    sprite_comps: Vec<SpriteComponent>,
-    pos_comps: Vec<PositionComponent>,
+    comp_lists: HashMap<TypeId, BorrowCell<Box<dyn AnyComponentList>>>,
+    resources: HashMap<TypeId, BorrowCell<Box<dyn Any + Send + Sync>>>,
+    event_updaters: Vec<fn(&EntityManager)>,
+    free_entities: Vec<u32>,
+    snapshot_types: Vec<SnapshotType>,
+    prefab_types: HashMap<String, PrefabType>,
+    /// Commands from finished systems, waiting to be applied.
+    commands: Commands,
+    /// Commands queued outside of systems, possibly from other threads.
+    shared_commands: Mutex<Commands>,
+    change_tick: u32
+}
+
+/// How to save and load one component type.
+struct SnapshotType {
+    name: String,
+    save: fn(&EntityManager, &mut SnapshotWriter),
+    load: fn(&mut EntityManager, &mut SnapshotReader) -> Result<(), SnapshotError>
+}
+
+/// How to add one component type from prefab fields.
+struct PrefabType {
+    fields: &'static [&'static str],
+    add: fn(&mut EntityManager, EntityId, &Yaml) -> Result<(), PrefabError>
+}
+
+/// Marks the start of a snapshot, followed by the format version.
+const SNAPSHOT_HEADER: &[u8] = b"SFSN";
+const SNAPSHOT_VERSION: u32 = 5;
+
+/// Reasons an entity's component can't be accessed.
+#[derive(Debug, PartialEq)]
+pub enum EntityError {
+    /// The entity doesn't have the component.
+    MissingComponent { entity_id: EntityId, component: &'static str },
+    /// The entity was destroyed, and its slot is free.
+    DeadEntity(EntityId),
+    /// The entity was destroyed, and its slot now holds a newer entity.
+    StaleGeneration { entity_id: EntityId, current: u32 }
+}
+
+impl fmt::Display for EntityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EntityError::MissingComponent { entity_id, component } => write!(f, "Entity {:?} has no {}.", entity_id, component),
+            EntityError::DeadEntity(entity_id) => write!(f, "Entity {:?} is dead.", entity_id),
+            EntityError::StaleGeneration { entity_id, current } => write!(f, "Entity {:?} is stale, its slot is now generation {}.", entity_id, current)
+        }
+    }
+}
+
+thread_local! {
+    /// The tick the system running on this thread last ran at.
+    /// Kept per thread, so systems running in parallel each see their own changes.
+    static LAST_CHANGE_TICK: Cell<u32> = const { Cell::new(0) };
+
+    /// Commands queued by the system running on this thread.
+    /// Kept per thread, so systems running in parallel each fill their own buffer.
+    static SYSTEM_COMMANDS: RefCell<Commands> = RefCell::new(Commands::new());
+
+    /// True while a system runs on this thread.
+    static IN_SYSTEM: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Adds commands to those queued by the system running on this thread.
+fn queue_system_commands(commands: Commands) {
+    SYSTEM_COMMANDS.with(|system_commands| system_commands.borrow_mut().append(commands));
+}
+
+/// Takes the commands queued by the system that ran on this thread.
+pub fn take_system_commands() -> Commands {
+    SYSTEM_COMMANDS.with(|system_commands| std::mem::take(&mut *system_commands.borrow_mut()))
+}
+
+/// Runs a function with changes made after `last_run` counting as new on this thread.
+pub fn with_last_change_tick<R, F: FnOnce() -> R>(last_run: u32, f: F) -> R {
+    let outer_last_tick = LAST_CHANGE_TICK.with(|tick| tick.replace(last_run));
+    let outer_in_system = IN_SYSTEM.with(|in_system| in_system.replace(true));
+    let result = f();
+    LAST_CHANGE_TICK.with(|tick| tick.set(outer_last_tick));
+    IN_SYSTEM.with(|in_system| in_system.set(outer_in_system));
+    result
 }
 
+/// A shared resource borrowed from the entity manager.
+pub type Res<'a, T> = Ref<'a, T>;
+
+/// A shared resource borrowed mutably from the entity manager.
+pub type ResMut<'a, T> = RefMut<'a, T>;
+
 const INITIAL_ENTITIES_LEN: usize = 32;
-const INITIAL_COMPS_LEN: usize = 32;
 
 impl EntityManager {
-    // TODO: This is an improvement, but make it less wordy
-    setup_comp!(LogComponent, log_comps, log_ind, add_log_comp, get_log_comp, get_log_comp_immut, get_next_free_log_index);
-    setup_comp!(SpriteComponent, sprite_comps, sprite_ind, add_sprite_comp, get_sprite_comp, get_sprite_comp_immut, get_next_free_sprite_index);
-    setup_comp!(PositionComponent, pos_comps, pos_ind, add_pos_comp, get_pos_comp, get_pos_comp_immut, get_next_free_pos_index);
-
     pub fn new() -> Self {
-        // Component lists should have at least 1 element
-        // Index 0 has the null component
-        let mut log_comps = vec![
-            LogComponent::uninit()
-        ];
-        log_comps.reserve(INITIAL_COMPS_LEN - 1);
-        let mut sprite_comps = vec![
-            SpriteComponent::uninit()
-        ];
-        sprite_comps.reserve(INITIAL_COMPS_LEN - 1);
-        let mut pos_comps = vec![
-            PositionComponent::uninit()
-        ];
-        pos_comps.reserve(INITIAL_COMPS_LEN - 1);
-
-        EntityManager {
+        let mut entity_mgr = EntityManager {
             entities: vec::Vec::with_capacity(INITIAL_ENTITIES_LEN),
-            log_comps,
-            sprite_comps,
-            pos_comps
+            comp_lists: HashMap::new(),
+            resources: HashMap::new(),
+            event_updaters: Vec::new(),
+            free_entities: Vec::new(),
+            snapshot_types: Vec::new(),
+            prefab_types: HashMap::new(),
+            commands: Commands::new(),
+            shared_commands: Mutex::new(Commands::new()),
+            change_tick: 1
+        };
+
+        // Built in components can always be saved
+        entity_mgr.register_snapshot::<LogComponent>("log");
+        entity_mgr.register_snapshot::<SpriteComponent>("sprite");
+        entity_mgr.register_snapshot::<PositionComponent>("position");
+        entity_mgr.register_snapshot::<PlayerComponent>("player");
+        entity_mgr.register_snapshot::<ParentComponent>("parent");
+        entity_mgr.register_snapshot::<ChildrenComponent>("children");
+
+        // Built in components can be used in prefabs
+        // Hierarchy components link entities, so they're set up in code instead
+        entity_mgr.register_prefab::<LogComponent>("log");
+        entity_mgr.register_prefab::<SpriteComponent>("sprite");
+        entity_mgr.register_prefab::<PositionComponent>("position");
+        entity_mgr.register_prefab::<PlayerComponent>("player");
+
+        entity_mgr
+    }
+
+    /// Lets a component type be saved in snapshots.
+    /// The name identifies the type inside the snapshot.
+    pub fn register_snapshot<T: Component + Snapshot>(&mut self, name: &str) {
+        self.snapshot_types.retain(|snapshot_type| snapshot_type.name != name);
+        self.snapshot_types.push(SnapshotType {
+            name: String::from(name),
+            save: save_comps::<T>,
+            load: load_comps::<T>
+        });
+    }
+
+    /// Saves all entities and registered components to bytes.
+    /// Resources and unregistered components are not saved.
+    pub fn save_snapshot(&self) -> Vec<u8> {
+        let mut writer = SnapshotWriter::new();
+        writer.write_bytes(SNAPSHOT_HEADER);
+        writer.write(&SNAPSHOT_VERSION);
+
+        writer.write(&(self.entities.len() as u32));
+        for entity in &self.entities {
+            writer.write(&entity.exists);
+            writer.write(&entity.id.generation);
+        }
+
+        writer.write(&(self.snapshot_types.len() as u32));
+        for snapshot_type in &self.snapshot_types {
+            writer.write(&snapshot_type.name);
+            (snapshot_type.save)(self, &mut writer);
+        }
+
+        writer.into_bytes()
+    }
+
+    /// Replaces all entities and components with those in a snapshot.
+    /// Resources are kept. On error, nothing is changed.
+    pub fn load_snapshot(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let mut reader = SnapshotReader::new(bytes);
+        if reader.read_bytes(SNAPSHOT_HEADER.len()).ok() != Some(SNAPSHOT_HEADER) {
+            return Err(SnapshotError::BadHeader);
+        }
+        let version = reader.read::<u32>()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::InvalidData(format!("Unsupported version {}.", version)));
+        }
+
+        // Load into a scratch manager, so a bad snapshot leaves us untouched
+        // Loaded components count as added now
+        let mut loaded = EntityManager::new();
+        loaded.change_tick = self.change_tick;
+        let entity_count = reader.read::<u32>()?;
+        for index in 0..entity_count {
+            let exists = reader.read::<bool>()?;
+            let generation = reader.read::<u32>()?;
+            loaded.entities.push(Entity {
+                id: EntityId {
+                    index,
+                    generation
+                },
+                exists
+            });
+        }
+
+        let type_count = reader.read::<u32>()?;
+        for _ in 0..type_count {
+            let name = reader.read::<String>()?;
+            match self.snapshot_types.iter().find(|snapshot_type| snapshot_type.name == name) {
+                Some(snapshot_type) => (snapshot_type.load)(&mut loaded, &mut reader)?,
+                None => return Err(SnapshotError::UnknownComponent(name))
+            }
+        }
+        if !reader.is_done() {
+            return Err(SnapshotError::InvalidData(String::from("Trailing bytes after snapshot.")));
+        }
+
+        loaded.free_entities = (0..entity_count).rev()
+            .filter(|&index| !loaded.entities[index as usize].exists)
+            .collect();
+
+        // The replaced components count as removed, so systems can clean up after them
+        let tick = self.change_tick;
+        for entity in self.entities.iter().filter(|entity| entity.exists) {
+            for comp_list in self.comp_lists.values_mut() {
+                comp_list.get_mut().remove_entity(entity.id, tick);
+            }
+        }
+        for (type_id, mut comp_list) in self.comp_lists.drain() {
+            match loaded.comp_lists.get_mut(&type_id) {
+                Some(loaded_list) => loaded_list.get_mut().append_removed(comp_list.get_mut().as_mut()),
+                None => {
+                    loaded.comp_lists.insert(type_id, comp_list);
+                }
+            }
+        }
+
+        self.entities = loaded.entities;
+        self.free_entities = loaded.free_entities;
+        self.comp_lists = loaded.comp_lists;
+        Ok(())
+    }
+
+    /// Lets a component type be used in prefabs under a name.
+    pub fn register_prefab<T: FromPrefab>(&mut self, name: &str) {
+        self.prefab_types.insert(String::from(name), PrefabType {
+            fields: T::FIELDS,
+            add: add_prefab_comp::<T>
+        });
+    }
+
+    /// Creates an entity from a prefab in the PrefabLibrary resource.
+    /// If the prefab has a position, it is moved to (x, y).
+    /// On error, no entity is created.
+    pub fn spawn_prefab(&mut self, name: &str, x: i32, y: i32) -> Result<EntityId, PrefabError> {
+        let prefab = self.res::<PrefabLibrary>().get(name)?.clone();
+
+        // Check every component before creating anything
+        let mut adders = Vec::new();
+        if let Some(comps) = prefab.as_hash() {
+            for (comp_name, fields) in comps {
+                let comp_name = comp_name.as_str().unwrap_or("");
+                match self.prefab_types.get(comp_name) {
+                    Some(prefab_type) => {
+                        prefab::check_fields(comp_name, fields, prefab_type.fields)?;
+                        adders.push((prefab_type.add, fields));
+                    },
+                    None => return Err(PrefabError::UnknownComponent {
+                        prefab: String::from(name),
+                        component: String::from(comp_name)
+                    })
+                }
+            }
+        }
+
+        let entity_id = self.create_entity();
+        for (add_comp, fields) in adders {
+            if let Err(err) = add_comp(self, entity_id, fields) {
+                self.destroy_entity(entity_id);
+                return Err(err);
+            }
+        }
+
+        if let Ok(pos_comp) = self.try_get_mut::<PositionComponent>(entity_id) {
+            pos_comp.x += x;
+            pos_comp.y += y;
+            pos_comp.prec_x += x as f32;
+            pos_comp.prec_y += y as f32;
+        }
+
+        Ok(entity_id)
+    }
+
+    /// Adds a shared resource, replacing any of the same type.
+    pub fn insert_resource<T: Send + Sync + 'static>(&mut self, resource: T) {
+        self.resources.insert(TypeId::of::<T>(), BorrowCell::new(Box::new(resource)));
+    }
+
+    /// Removes a shared resource and returns it.
+    pub fn remove_resource<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.resources.remove(&TypeId::of::<T>()).map(|resource| {
+            *resource.into_inner().downcast::<T>().unwrap()
+        })
+    }
+
+    /// Returns true if the resource exists.
+    pub fn has_resource<T: Send + Sync + 'static>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Borrows a shared resource.
+    /// Panics if it doesn't exist or is mutably borrowed.
+    pub fn res<T: Send + Sync + 'static>(&self) -> Res<'_, T> {
+        match self.resources.get(&TypeId::of::<T>()) {
+            Some(resource) => Ref::map(resource.borrow(), |resource| resource.downcast_ref::<T>().unwrap()),
+            None => panic!("Resource {} does not exist.", any::type_name::<T>())
+        }
+    }
+
+    /// Borrows a shared resource mutably.
+    /// Panics if it doesn't exist or is already borrowed.
+    pub fn res_mut<T: Send + Sync + 'static>(&self) -> ResMut<'_, T> {
+        match self.resources.get(&TypeId::of::<T>()) {
+            Some(resource) => RefMut::map(resource.borrow_mut(), |resource| resource.downcast_mut::<T>().unwrap()),
+            None => panic!("Resource {} does not exist.", any::type_name::<T>())
+        }
+    }
+
+    /// Sets up a channel for an event type.
+    /// Does nothing if the channel already exists.
+    pub fn add_event<T: Send + Sync + 'static>(&mut self) {
+        if !self.has_resource::<Events<T>>() {
+            self.insert_resource(Events::<T>::new());
+            self.event_updaters.push(update_event_channel::<T>);
+        }
+    }
+
+    /// Returns a writer for an event type.
+    /// Panics if the channel wasn't added.
+    pub fn event_writer<T: Send + Sync + 'static>(&self) -> EventWriter<'_, T> {
+        if !self.has_resource::<Events<T>>() {
+            panic!("Resource {} does not exist.", any::type_name::<Events<T>>());
+        }
+        EventWriter::new(self.commands())
+    }
+
+    /// Swaps the buffers of every event channel.
+    /// Run once per tick by the schedule.
+    pub fn update_events(&self) {
+        for update in &self.event_updaters {
+            update(self);
+        }
+    }
+
+    /// Returns a command buffer, for queueing structural changes.
+    /// The commands are kept with the running system's others, so systems
+    /// never share a buffer, even when running in parallel.
+    pub fn commands(&self) -> SystemCommands<'_> {
+        SystemCommands::new(self)
+    }
+
+    /// Queues commands made on this thread.
+    /// Outside of a system, they go to a shared queue, so commands from threads the schedule
+    /// doesn't run systems on aren't lost.
+    pub fn queue_thread_commands(&self, commands: Commands) {
+        if IN_SYSTEM.with(|in_system| in_system.get()) {
+            queue_system_commands(commands);
+        }
+        else {
+            self.shared_commands.lock().unwrap().append(commands);
+        }
+    }
+
+    /// Queues commands from a finished system, to apply after those already queued.
+    pub fn queue_commands(&mut self, commands: Commands) {
+        self.commands.append(commands);
+    }
+
+    /// Applies all queued commands, followed by any this thread's system queued,
+    /// then those queued outside of systems.
+    /// The schedule does this after every stage.
+    pub fn apply_commands(&mut self) {
+        let mut commands = std::mem::take(&mut self.commands);
+        commands.append(take_system_commands());
+        commands.append(std::mem::take(self.shared_commands.get_mut().unwrap()));
+        commands.apply(self);
+    }
+
+    /// Adds a component to the entity.
+    /// If the entity already has one, it is reset.
+    pub fn add<T: Component>(&mut self, entity_id: EntityId) {
+        let entity_index = self.entity_index(entity_id) as u32;
+        let tick = self.change_tick;
+        self.comp_list_mut::<T>().add(entity_index, tick);
+    }
+
+    /// Adds an initialized component to the entity.
+    /// If the entity already has one, it is replaced.
+    pub fn insert<T: Component>(&mut self, entity_id: EntityId, comp: T) {
+        let entity_index = self.entity_index(entity_id) as u32;
+        let tick = self.change_tick;
+        self.comp_list_mut::<T>().insert(entity_index, comp, tick);
+    }
+
+    /// Removes a component from the entity, if it has one.
+    pub fn remove<T: Component>(&mut self, entity_id: EntityId) {
+        // Checked so stale IDs panic like everywhere else
+        self.entity_index(entity_id);
+        let tick = self.change_tick;
+        self.comp_list_mut::<T>().remove_entity(entity_id, tick);
+    }
+
+    /// Returns true if the entity has the component.
+    pub fn has<T: Component>(&self, entity_id: EntityId) -> bool {
+        let entity_index = self.entity_index(entity_id) as u32;
+        match self.borrow_comp_list::<T>() {
+            Some(list) => list.has(entity_index),
+            None => false
+        }
+    }
+
+    /// Returns an entity's component.
+    /// Panics if the entity is dead or doesn't have one.
+    pub fn get<T: Component>(&self, entity_id: EntityId) -> Ref<'_, T> {
+        self.try_get(entity_id).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Returns an entity's component mutably, marking it changed.
+    /// Panics if the entity is dead or doesn't have one.
+    pub fn get_mut<T: Component>(&mut self, entity_id: EntityId) -> &mut T {
+        self.try_get_mut(entity_id).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Returns an entity's component, or why it can't be accessed.
+    pub fn try_get<T: Component>(&self, entity_id: EntityId) -> Result<Ref<'_, T>, EntityError> {
+        let entity_index = self.check_alive(entity_id)? as u32;
+        let list = match self.borrow_comp_list::<T>() {
+            Some(list) if list.has(entity_index) => list,
+            _ => return Err(missing_comp::<T>(entity_id))
+        };
+        Ok(Ref::map(list, |list| list.get(entity_index).unwrap()))
+    }
+
+    /// Returns an entity's component mutably, or why it can't be accessed.
+    /// Marks the component changed.
+    pub fn try_get_mut<T: Component>(&mut self, entity_id: EntityId) -> Result<&mut T, EntityError> {
+        let entity_index = self.check_alive(entity_id)? as u32;
+        let tick = self.change_tick;
+        self.comp_lists.get_mut(&TypeId::of::<T>())
+            .and_then(|list| list.get_mut().as_any_mut().downcast_mut::<ComponentList<T>>().unwrap().get_mut(entity_index, tick))
+            .ok_or_else(|| missing_comp::<T>(entity_id))
+    }
+
+    /// Returns entities that lost a component since the current system last ran.
+    /// This includes entities that were destroyed.
+    pub fn removed<T: Component>(&self) -> Vec<EntityId> {
+        match self.borrow_comp_list::<T>() {
+            Some(list) => list.removed(self.last_change_tick()).collect(),
+            None => Vec::new()
         }
     }
 
+    /// Returns the tick that changes are currently stamped with.
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+
+    /// Returns the tick the current system last ran at.
+    /// Changes after it count as added or changed.
+    pub fn last_change_tick(&self) -> u32 {
+        LAST_CHANGE_TICK.with(|tick| tick.get())
+    }
+
+    /// Moves on to a new tick, and returns it.
+    /// Changes are stamped with the new tick from now on.
+    pub fn advance_change_tick(&mut self) -> u32 {
+        self.change_tick += 1;
+        self.change_tick
+    }
+
+    /// Runs a system so it sees changes made since `last_run`.
+    /// Returns the tick to pass as `last_run` next time.
+    pub fn run_system<F: FnOnce(&mut EntityManager)>(&mut self, last_run: u32, system: F) -> u32 {
+        let this_run = self.advance_change_tick();
+        with_last_change_tick(last_run, || system(self));
+
+        // Changes made after the system count as new to it
+        self.advance_change_tick();
+        this_run
+    }
+
+    /// Forgets component removals made at or before a tick.
+    /// The schedule does this once every system has had a chance to see them.
+    pub fn clear_removed(&mut self, tick: u32) {
+        for comp_list in self.comp_lists.values_mut() {
+            comp_list.get_mut().clear_removed(tick);
+        }
+    }
+
+    /// Iterates over all entities with a set of components.
+    /// e.g. `entity_mgr.query::<(&mut SpriteComponent, &PositionComponent)>()`
+    pub fn query<Q: Query>(&self) -> QueryBorrow<'_, Q> {
+        QueryBorrow::new(self)
+    }
+
+    /// Borrows the list for a component type.
+    /// Returns None if the component was never added to any entity.
+    pub fn borrow_comp_list<T: Component>(&self) -> Option<Ref<'_, ComponentList<T>>> {
+        self.comp_lists.get(&TypeId::of::<T>()).map(|list| {
+            Ref::map(list.borrow(), |list| list.as_any().downcast_ref::<ComponentList<T>>().unwrap())
+        })
+    }
+
+    /// Borrows the list for a component type mutably.
+    /// Returns None if the component was never added to any entity.
+    pub fn borrow_comp_list_mut<T: Component>(&self) -> Option<RefMut<'_, ComponentList<T>>> {
+        self.comp_lists.get(&TypeId::of::<T>()).map(|list| {
+            RefMut::map(list.borrow_mut(), |list| list.as_any_mut().downcast_mut::<ComponentList<T>>().unwrap())
+        })
+    }
+
+    /// Returns the list for a component type mutably.
+    /// Creates the list if it doesn't exist yet.
+    fn comp_list_mut<T: Component>(&mut self) -> &mut ComponentList<T> {
+        self.comp_lists.entry(TypeId::of::<T>())
+            .or_insert_with(|| BorrowCell::new(Box::new(ComponentList::<T>::new())))
+            .get_mut()
+            .as_any_mut()
+            .downcast_mut::<ComponentList<T>>()
+            .unwrap()
+    }
+
     /// Creates a new entity and returns its ID.
-    pub fn create_entity(&mut self) -> u32 {
+    pub fn create_entity(&mut self) -> EntityId {
         // Create a new Entity entry
         // By default, has no components
-        let entity_id = self.get_next_free_id();
-        let mut entity = Entity {
+        // The generation carries over from the last entity in this slot
+        let entity_index = self.get_next_free_id();
+        let entity_id = EntityId {
+            index: entity_index,
+            generation: self.entities[entity_index as usize].id.generation
+        };
+        let entity = Entity {
             id: entity_id,
             exists: true,
-            log_ind: 0,
-            sprite_ind: 0,
-            pos_ind: 0,
-            use_draw: false,
-            use_log: false,
-            use_player: false,
         };
-        self.entities[entity_id as usize] = entity;
+        self.entities[entity_index as usize] = entity;
 
         entity_id
     }
 
+    /// Destroys an entity and frees all of its components.
+    /// Children are destroyed along with it.
+    /// Any IDs still referring to the entity become stale.
+    pub fn destroy_entity(&mut self, entity_id: EntityId) {
+        self.remove_parent(entity_id);
+        if self.has::<ChildrenComponent>(entity_id) {
+            let children = std::mem::take(&mut self.get_mut::<ChildrenComponent>(entity_id).children);
+            for child_id in children {
+                if self.is_alive(child_id) {
+                    self.destroy_entity(child_id);
+                }
+            }
+        }
+
+        let entity_index = self.entity_index(entity_id);
+        for comp_list in self.comp_lists.values_mut() {
+            comp_list.get_mut().remove_entity(entity_id, self.change_tick);
+        }
+
+        // Bump the generation so old IDs no longer match
+        let mut dead_entity = Entity::uninit();
+        dead_entity.id = EntityId {
+            index: entity_id.index,
+            generation: entity_id.generation.wrapping_add(1)
+        };
+        self.entities[entity_index] = dead_entity;
+        self.free_entities.push(entity_id.index);
+    }
+
+    /// Attaches an entity to a parent, at an offset from the parent's position.
+    /// Replaces any previous parent.
+    pub fn set_parent(&mut self, child_id: EntityId, parent_id: EntityId, offset_x: i32, offset_y: i32) {
+        // Walk up from the new parent to make sure we don't create a cycle
+        let mut ancestor_id = parent_id;
+        loop {
+            assert!(ancestor_id != child_id, "Entity {:?} can't be its own ancestor.", child_id);
+            if !self.has::<ParentComponent>(ancestor_id) {
+                break;
+            }
+            ancestor_id = self.get::<ParentComponent>(ancestor_id).parent;
+        }
+
+        self.remove_parent(child_id);
+        self.insert(child_id, ParentComponent {
+            parent: parent_id,
+            offset_x,
+            offset_y
+        });
+        if !self.has::<ChildrenComponent>(parent_id) {
+            self.add::<ChildrenComponent>(parent_id);
+        }
+        self.get_mut::<ChildrenComponent>(parent_id).children.push(child_id);
+    }
+
+    /// Detaches an entity from its parent, if it has one.
+    pub fn remove_parent(&mut self, child_id: EntityId) {
+        if !self.has::<ParentComponent>(child_id) {
+            return;
+        }
+
+        let parent_id = self.get::<ParentComponent>(child_id).parent;
+        self.remove::<ParentComponent>(child_id);
+        if self.is_alive(parent_id) && self.has::<ChildrenComponent>(parent_id) {
+            self.get_mut::<ChildrenComponent>(parent_id).children.retain(|&id| id != child_id);
+        }
+    }
+
+    /// Returns true if the ID refers to a living entity.
+    pub fn is_alive(&self, entity_id: EntityId) -> bool {
+        match self.entities.get(entity_id.index as usize) {
+            Some(entity) => entity.exists && entity.id.generation == entity_id.generation,
+            None => false
+        }
+    }
+
+    /// Returns the index of a living entity.
+    /// Panics if the ID is stale.
+    fn entity_index(&self, entity_id: EntityId) -> usize {
+        self.check_alive(entity_id).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Returns the index of a living entity, or why it isn't alive.
+    fn check_alive(&self, entity_id: EntityId) -> Result<usize, EntityError> {
+        match self.entities.get(entity_id.index as usize) {
+            Some(entity) if entity.exists && entity.id.generation == entity_id.generation => Ok(entity_id.index as usize),
+            Some(entity) if entity.exists => Err(EntityError::StaleGeneration {
+                entity_id,
+                current: entity.id.generation
+            }),
+            _ => Err(EntityError::DeadEntity(entity_id))
+        }
+    }
+
     /// Returns the next free ID.
     /// May expand entity list.
     fn get_next_free_id(&mut self) -> u32 {
-        match self.entities.iter().find(|&&entity| {!entity.exists}) {
-            Some(entity) => entity.id,
+        match self.free_entities.pop() {
+            Some(ind) => ind,
             None => {
                 self.entities.push(Entity::uninit());
                 self.entities.len() as u32 - 1
             },
         }
     }
+}
 
-    /// Activates the logging system for the entity.
-    pub fn set_use_log(&mut self, entity_id: u32) {
-        self.entities[entity_id as usize].use_log = true;
+impl Default for EntityManager {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    /// Activates the draw system for the entity.
-    pub fn set_use_draw(&mut self, entity_id: u32) {
-        self.entities[entity_id as usize].use_draw = true;
+/// Creates a MissingComponent error.
+fn missing_comp<T: Component>(entity_id: EntityId) -> EntityError {
+    EntityError::MissingComponent {
+        entity_id,
+        component: any::type_name::<T>()
     }
+}
 
-    /// Activates the player system for the entity.
-    pub fn set_use_player(&mut self, entity_id: u32) {
-        self.entities[entity_id as usize].use_player = true;
+/// Saves every component of one type.
+fn save_comps<T: Component + Snapshot>(entity_mgr: &EntityManager, writer: &mut SnapshotWriter) {
+    match entity_mgr.borrow_comp_list::<T>() {
+        Some(list) => {
+            writer.write(&(list.len() as u32));
+            for (entity_index, comp) in list.iter() {
+                writer.write(&entity_index);
+                writer.write(comp);
+            }
+        },
+        None => writer.write(&0_u32)
     }
 }
 
-/// Contains indices for components.
+/// Loads every component of one type.
+fn load_comps<T: Component + Snapshot>(entity_mgr: &mut EntityManager, reader: &mut SnapshotReader) -> Result<(), SnapshotError> {
+    let count = reader.read::<u32>()?;
+    for _ in 0..count {
+        let entity_index = reader.read::<u32>()?;
+        match entity_mgr.entities.get(entity_index as usize) {
+            Some(entity) if entity.exists => {},
+            _ => return Err(SnapshotError::InvalidData(format!("Component {} belongs to missing entity {}.", any::type_name::<T>(), entity_index)))
+        }
+        let comp = reader.read::<T>()?;
+        let tick = entity_mgr.change_tick;
+        entity_mgr.comp_list_mut::<T>().insert(entity_index, comp, tick);
+    }
+    Ok(())
+}
+
+/// Adds a component to an entity from prefab fields.
+fn add_prefab_comp<T: FromPrefab>(entity_mgr: &mut EntityManager, entity_id: EntityId, fields: &Yaml) -> Result<(), PrefabError> {
+    let mut comp = T::uninit();
+    comp.apply_fields(fields)?;
+    entity_mgr.insert(entity_id, comp);
+    Ok(())
+}
+
+/// Swaps the buffers of an event channel.
+fn update_event_channel<T: Send + Sync + 'static>(entity_mgr: &EntityManager) {
+    entity_mgr.res_mut::<Events<T>>().update();
+}
+
+/// Identifies an entity.
+/// The generation changes whenever the entity's slot is reused, so stale IDs can be detected.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EntityId {
+    pub index: u32,
+    pub generation: u32
+}
+
+impl Snapshot for EntityId {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.index);
+        writer.write(&self.generation);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        Ok(Self {
+            index: reader.read()?,
+            generation: reader.read()?
+        })
+    }
+}
+
+impl EntityId {
+    /// An ID that never refers to a living entity.
+    pub const NULL: EntityId = EntityId {
+        index: u32::MAX,
+        generation: 0
+    };
+}
+
+/// Describes an entity.
+/// Its components are stored in the manager's component lists.
+/// Systems select entities by which components they have.
 #[derive(Debug, Copy, Clone)]
 pub struct Entity {
-    pub id: u32,
-    pub exists: bool,
-    log_ind: u32,
-    sprite_ind: u32,
-    pos_ind: u32,
-    pub use_log: bool,
-    pub use_draw: bool,
-    pub use_player: bool
+    pub id: EntityId,
+    pub exists: bool
 }
 
 impl Entity {
     /// Creates an uninitialized entity.
     fn uninit() -> Self {
         Self {
-            id: 0,
-            exists: false,
-            log_ind: 0,
-            sprite_ind: 0,
-            pos_ind: 0,
-            use_log: false,
-            use_draw: false,
-            use_player: false
+            id: EntityId {
+                index: 0,
+                generation: 0
+            },
+            exists: false
+        }
+    }
+}
+
+/// Test if resources can be borrowed and removed.
+#[test]
+fn test_resources() {
+    let mut entity_mgr = EntityManager::new();
+    entity_mgr.insert_resource(5_u32);
+    *entity_mgr.res_mut::<u32>() += 1;
+    assert_eq!(*entity_mgr.res::<u32>(), 6);
+    assert_eq!(entity_mgr.remove_resource::<u32>(), Some(6));
+    assert!(!entity_mgr.has_resource::<u32>());
+}
+
+/// Test if destroyed entities free their slot and invalidate old IDs.
+#[test]
+fn test_destroy_entity() {
+    use crate::components::PositionComponent;
+
+    let mut entity_mgr = EntityManager::new();
+    let entity_id = entity_mgr.create_entity();
+    entity_mgr.add::<PositionComponent>(entity_id);
+    entity_mgr.get_mut::<PositionComponent>(entity_id).x = 5;
+    entity_mgr.destroy_entity(entity_id);
+    assert!(!entity_mgr.is_alive(entity_id));
+
+    // The slot is reused, but with a new generation and a fresh component
+    let new_id = entity_mgr.create_entity();
+    entity_mgr.add::<PositionComponent>(new_id);
+    assert_eq!(new_id.index, entity_id.index);
+    assert_ne!(new_id.generation, entity_id.generation);
+    assert!(entity_mgr.is_alive(new_id));
+    assert_eq!(entity_mgr.get::<PositionComponent>(new_id).x, 0);
+}
+
+/// Test if children follow their parent and are destroyed with it.
+#[test]
+fn test_hierarchy() {
+    use crate::components::PositionComponent;
+    use crate::systems::transform_system;
+
+    let mut entity_mgr = EntityManager::new();
+    let parent_id = entity_mgr.create_entity();
+    entity_mgr.add::<PositionComponent>(parent_id);
+    let child_id = entity_mgr.create_entity();
+    entity_mgr.add::<PositionComponent>(child_id);
+    entity_mgr.set_parent(child_id, parent_id, 0, -16);
+    let grandchild_id = entity_mgr.create_entity();
+    entity_mgr.add::<PositionComponent>(grandchild_id);
+    entity_mgr.set_parent(grandchild_id, child_id, 4, 0);
+
+    entity_mgr.get_mut::<PositionComponent>(parent_id).x = 10;
+    entity_mgr.get_mut::<PositionComponent>(parent_id).y = 100;
+    transform_system::update(&mut entity_mgr);
+    assert_eq!(entity_mgr.get::<PositionComponent>(child_id).y, 84);
+    assert_eq!(entity_mgr.get::<PositionComponent>(grandchild_id).x, 14);
+
+    entity_mgr.destroy_entity(parent_id);
+    assert!(!entity_mgr.is_alive(child_id));
+    assert!(!entity_mgr.is_alive(grandchild_id));
+}
+
+/// Test if a snapshot restores entities and components exactly.
+#[test]
+fn test_snapshot() {
+    use crate::query::Added;
+
+    let mut entity_mgr = EntityManager::new();
+    let old_id = entity_mgr.create_entity();
+    entity_mgr.destroy_entity(old_id);
+    let player_id = entity_mgr.create_entity();
+    entity_mgr.add::<PlayerComponent>(player_id);
+    entity_mgr.add::<PositionComponent>(player_id);
+    entity_mgr.get_mut::<PositionComponent>(player_id).prec_x = 12.75;
+    let map_id = entity_mgr.create_entity();
+    entity_mgr.add::<SpriteComponent>(map_id);
+    entity_mgr.get_mut::<SpriteComponent>(map_id).tilemap = Some(vec![4, 5, 1, 2]);
+    entity_mgr.get_mut::<SpriteComponent>(map_id).tilemap_width = 2;
+    entity_mgr.get_mut::<SpriteComponent>(map_id).tilemap_height = 2;
+    entity_mgr.get_mut::<SpriteComponent>(map_id).quad_id = 5;
+
+    let bytes = entity_mgr.save_snapshot();
+    entity_mgr.get_mut::<PositionComponent>(player_id).prec_x = 0.0;
+    entity_mgr.destroy_entity(map_id);
+    let last_run = entity_mgr.change_tick();
+    entity_mgr.advance_change_tick();
+    entity_mgr.load_snapshot(&bytes).unwrap();
+
+    // Replaced components count as removed, and loaded ones as added
+    // Loaded sprites get a new quad, rather than sharing the one they were saved with
+    with_last_change_tick(last_run, || {
+        assert_eq!(entity_mgr.removed::<PositionComponent>(), vec![player_id]);
+        assert_eq!(entity_mgr.query::<Added<&SpriteComponent>>().iter().count(), 1);
+    });
+    assert_eq!(entity_mgr.get::<SpriteComponent>(map_id).quad_id, 0);
+
+    assert!(!entity_mgr.is_alive(old_id));
+    assert!(entity_mgr.has::<PlayerComponent>(player_id));
+    assert_eq!(entity_mgr.get::<PositionComponent>(player_id).prec_x, 12.75);
+    assert_eq!(entity_mgr.get::<SpriteComponent>(map_id).tilemap, Some(vec![4, 5, 1, 2]));
+    assert_eq!(entity_mgr.save_snapshot(), bytes);
+
+    // Truncated snapshots are rejected without changing anything
+    assert_eq!(entity_mgr.load_snapshot(&bytes[..bytes.len() - 1]), Err(SnapshotError::UnexpectedEnd));
+    assert!(entity_mgr.is_alive(map_id));
+
+    // So are tilemaps that don't match their size
+    entity_mgr.get_mut::<SpriteComponent>(map_id).tilemap_width = 3;
+    let bad_bytes = entity_mgr.save_snapshot();
+    entity_mgr.get_mut::<SpriteComponent>(map_id).tilemap_width = 2;
+    assert!(matches!(entity_mgr.load_snapshot(&bad_bytes), Err(SnapshotError::InvalidData(_))));
+    assert_eq!(entity_mgr.get::<SpriteComponent>(map_id).tilemap_width, 2);
+}
+
+/// Test if prefabs spawn with their components and report bad definitions.
+#[test]
+fn test_spawn_prefab() {
+    let mut entity_mgr = EntityManager::new();
+    entity_mgr.insert_resource(PrefabLibrary::parse("
+ferris:
+  player:
+  position:
+    y: -4
+  sprite:
+    tex_name: assets/ferris.png
+typo:
+  sprite:
+    texture: assets/ferris.png
+unknown:
+  position:
+  wings:
+").unwrap());
+
+    let ferris_id = entity_mgr.spawn_prefab("ferris", 32, 64).unwrap();
+    assert!(entity_mgr.has::<PlayerComponent>(ferris_id));
+    assert_eq!(entity_mgr.get::<PositionComponent>(ferris_id).y, 60);
+    assert_eq!(entity_mgr.get::<PositionComponent>(ferris_id).prec_x, 32.0);
+    assert_eq!(entity_mgr.get::<SpriteComponent>(ferris_id).tex_name, "assets/ferris.png");
+
+    assert_eq!(entity_mgr.spawn_prefab("missing", 0, 0), Err(PrefabError::UnknownPrefab(String::from("missing"))));
+    assert!(matches!(entity_mgr.spawn_prefab("typo", 0, 0), Err(PrefabError::UnknownField { .. })));
+    assert!(matches!(entity_mgr.spawn_prefab("unknown", 0, 0), Err(PrefabError::UnknownComponent { .. })));
+
+    // Failed spawns don't leave entities behind
+    assert_eq!(entity_mgr.entities.iter().filter(|entity| entity.exists).count(), 1);
+}
+
+/// Test if accessors report why a component can't be reached.
+#[test]
+fn test_try_get() {
+    let mut entity_mgr = EntityManager::new();
+    let entity_id = entity_mgr.create_entity();
+    entity_mgr.add::<PositionComponent>(entity_id);
+    let bare_id = entity_mgr.create_entity();
+
+    entity_mgr.try_get_mut::<PositionComponent>(entity_id).unwrap().x = 3;
+    assert_eq!(entity_mgr.try_get::<PositionComponent>(entity_id).unwrap().x, 3);
+    assert!(matches!(entity_mgr.try_get::<PositionComponent>(bare_id), Err(EntityError::MissingComponent { .. })));
+    assert!(matches!(entity_mgr.try_get_mut::<SpriteComponent>(bare_id), Err(EntityError::MissingComponent { .. })));
+
+    entity_mgr.destroy_entity(entity_id);
+    assert!(matches!(entity_mgr.try_get::<PositionComponent>(entity_id), Err(EntityError::DeadEntity(_))));
+    let new_id = entity_mgr.create_entity();
+    assert!(matches!(entity_mgr.try_get_mut::<PositionComponent>(entity_id), Err(EntityError::StaleGeneration { current, .. }) if current == new_id.generation));
+}
+
+/// Times storage operations at scale.
+/// Run with `cargo test --release bench_storage -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn bench_storage() {
+    use std::time::Instant;
+
+    for &count in &[10_000, 100_000] {
+        let mut entity_mgr = EntityManager::new();
+
+        let start = Instant::now();
+        let ids: Vec<EntityId> = (0..count).map(|i| {
+            let entity_id = entity_mgr.create_entity();
+            entity_mgr.add::<PositionComponent>(entity_id);
+            if i % 10 == 0 {
+                entity_mgr.add::<PlayerComponent>(entity_id);
+            }
+            entity_id
+        }).collect();
+        println!("{} entities: create {:?}", count, start.elapsed());
+
+        let start = Instant::now();
+        for mut pos_comp in entity_mgr.query::<&mut PositionComponent>().iter() {
+            pos_comp.x += 1;
         }
+        println!("{} entities: iterate all {:?}", count, start.elapsed());
+
+        let start = Instant::now();
+        let sparse_count = entity_mgr.query::<(&PlayerComponent, &PositionComponent)>().iter().count();
+        println!("{} entities: iterate {} players {:?}", count, sparse_count, start.elapsed());
+
+        // Free and reuse every other slot
+        let start = Instant::now();
+        for &entity_id in ids.iter().step_by(2) {
+            entity_mgr.destroy_entity(entity_id);
+        }
+        for _ in ids.iter().step_by(2) {
+            let entity_id = entity_mgr.create_entity();
+            entity_mgr.add::<PositionComponent>(entity_id);
+        }
+        println!("{} entities: destroy and recreate half {:?}", count, start.elapsed());
+        assert_eq!(entity_mgr.entities.len(), count);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,124 @@
+///
+/// Typed event channels for systems to talk to each other.
+/// Events are double buffered, so they live until the end of the next tick.
+/// Sent events are queued like commands, so they arrive once the sender's stage finishes.
+///
+
+use std::marker::PhantomData;
+
+use crate::commands::SystemCommands;
+
+pub struct Events<T> {
+    prev: Vec<T>,
+    curr: Vec<T>,
+    prev_start: usize,
+    curr_start: usize,
+    event_count: usize
+}
+
+impl<T> Events<T> {
+    pub fn new() -> Self {
+        Self {
+            prev: Vec::new(),
+            curr: Vec::new(),
+            prev_start: 0,
+            curr_start: 0,
+            event_count: 0
+        }
+    }
+
+    /// Sends an event.
+    pub fn send(&mut self, event: T) {
+        self.curr.push(event);
+        self.event_count += 1;
+    }
+
+    /// Swaps buffers, dropping events sent before the last update.
+    /// Should be run once per tick.
+    pub fn update(&mut self) {
+        self.prev = std::mem::take(&mut self.curr);
+        self.prev_start = self.curr_start;
+        self.curr_start = self.event_count;
+    }
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sends events of one type.
+/// Doesn't borrow the channel, so systems sending the same events can run in parallel.
+pub struct EventWriter<'a, T> {
+    commands: SystemCommands<'a>,
+    event_type: PhantomData<fn(T)>
+}
+
+impl<'a, T: Send + Sync + 'static> EventWriter<'a, T> {
+    pub fn new(commands: SystemCommands<'a>) -> Self {
+        Self {
+            commands,
+            event_type: PhantomData
+        }
+    }
+
+    /// Queues an event to send when commands are applied.
+    pub fn send(&mut self, event: T) {
+        self.commands.push(move |entity_mgr| entity_mgr.res_mut::<Events<T>>().send(event));
+    }
+}
+
+/// Reads events of one type.
+/// Each reader keeps its own place, so every reader sees every event once.
+pub struct EventReader<T> {
+    last_count: usize,
+    event_type: PhantomData<fn() -> T>
+}
+
+impl<T> EventReader<T> {
+    pub fn new() -> Self {
+        Self {
+            last_count: 0,
+            event_type: PhantomData
+        }
+    }
+
+    /// Iterates over events this reader hasn't seen yet.
+    pub fn iter<'a>(&mut self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> {
+        // Skip events that were already read in either buffer
+        let last_count = self.last_count;
+        let prev_skip = last_count.saturating_sub(events.prev_start).min(events.prev.len());
+        let curr_skip = last_count.saturating_sub(events.curr_start).min(events.curr.len());
+        self.last_count = events.event_count;
+
+        events.prev[prev_skip..].iter().chain(events.curr[curr_skip..].iter())
+    }
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Test if events are seen once per reader and expire after two updates.
+#[test]
+fn test_events() {
+    let mut events = Events::new();
+    let mut reader = EventReader::new();
+    let mut late_reader = EventReader::new();
+
+    events.send(1);
+    events.update();
+    events.send(2);
+    assert_eq!(reader.iter(&events).collect::<Vec<_>>(), vec![&1, &2]);
+    assert_eq!(reader.iter(&events).count(), 0);
+
+    events.update();
+    events.send(3);
+    assert_eq!(reader.iter(&events).collect::<Vec<_>>(), vec![&3]);
+
+    // The first event has expired
+    assert_eq!(late_reader.iter(&events).collect::<Vec<_>>(), vec![&2, &3]);
+}
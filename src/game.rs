@@ -9,25 +9,38 @@ use winit::event;
 use winit::window;
 use timer;
 
-use crate::systems::{draw_system, logging_system, player_system};
-
-use super::entity_manager;
-use super::input_manager;
-use super::renderer;
+use super_ferris_game::components::{LogComponent, PlayerComponent, PositionComponent, SpriteComponent};
+use super_ferris_game::camera::Camera;
+use super_ferris_game::systems::{camera_system, draw_system, input_system, logging_system, player_system, transform_system};
+use super_ferris_game::entity_manager;
+use super_ferris_game::input_manager::{self, InputManager};
+use super_ferris_game::level::{self, Level, LevelBounds};
+use super_ferris_game::prefab::PrefabLibrary;
+use super_ferris_game::renderer;
+use super_ferris_game::schedule::{self, Stage, SystemDesc};
 
 pub struct Game {
-
+    level_path: String
 }
 
 impl Game {
-    pub fn new() -> Self {
+    pub fn new(level_path: &str) -> Self {
         Game {
-            
+            level_path: String::from(level_path)
         }
     }
 
     /// Sets up everything and runs until game stops.
     pub fn run(&mut self) {
+        // Load level before opening a window, so bad levels fail fast
+        let level = match Level::load(&self.level_path) {
+            Ok(level) => level,
+            Err(err) => {
+                eprintln!("{}", err);
+                return;
+            }
+        };
+
         // Create event loop and window
         let evt_loop = event_loop::EventLoop::new();
         let window = window::WindowBuilder::new()
@@ -41,77 +54,68 @@ impl Game {
             .expect("Could not create window.");
 
         // Set up input manager
-        let input_mgr_mut = sync::Arc::new(sync::Mutex::new(input_manager::InputManager::new()));
-        let mut input_mgr = input_mgr_mut.lock().unwrap();
+        let mut input_mgr = input_manager::InputManager::new();
         input_mgr.map_key_to_button(event::VirtualKeyCode::Left, input_manager::ButtonCode::LEFT);
         input_mgr.map_key_to_button(event::VirtualKeyCode::Right, input_manager::ButtonCode::RIGHT);
         input_mgr.map_key_to_button(event::VirtualKeyCode::Up, input_manager::ButtonCode::UP);
         input_mgr.map_key_to_button(event::VirtualKeyCode::Down, input_manager::ButtonCode::DOWN);
         input_mgr.map_key_to_button(event::VirtualKeyCode::X, input_manager::ButtonCode::A);
         input_mgr.map_key_to_button(event::VirtualKeyCode::Z, input_manager::ButtonCode::B);
-        drop(input_mgr);
 
         // Set up game framework
-        let entity_mgr_mut = sync::Arc::new(sync::Mutex::new(entity_manager::EntityManager::new()));
-        let mut renderer = futures::executor::block_on(renderer::Renderer::new(&window));
-
-        // Load map
-        let mut tilemap: Vec<u32> = Vec::new();
-        let level_str = include_str!("../assets/level.txt");
-        let mut prev_c = ' ';
-        let map_width = level_str.find('\n').expect("Couldn't find newline.") as u32;
-        let map_height = level_str.lines().count() as u32;
-        for c in level_str.chars() {
-            if c == '\n' {
-                continue;
-            }
-
-            let curr_index = match c {
-                'x' => 4,
-                ' ' => 5,
-                'c' => if prev_c == 'c' {2} else {1},
-                'b' => if prev_c == 'b' {3} else {7},
-                _ => 5
-            };
-            tilemap.push(curr_index);
-            prev_c = c;
-        }
+        let mut entity_mgr = entity_manager::EntityManager::new();
+        entity_mgr.insert_resource(input_mgr);
+        entity_mgr.insert_resource(level.bounds());
+        entity_mgr.insert_resource(Camera::new((renderer::WIN_WIDTH / renderer::WIN_SCALE) as f32, (renderer::WIN_HEIGHT / renderer::WIN_SCALE) as f32));
+        entity_mgr.add_event::<player_system::PlayerEvent>();
+        entity_mgr.insert_resource(PrefabLibrary::load("assets/prefabs.yaml").expect("Could not load prefabs."));
+        entity_mgr.insert_resource(futures::executor::block_on(renderer::Renderer::new(&window)));
 
         // Create entities
-        let mut entity_mgr = entity_mgr_mut.lock().unwrap();
-        let entity_id = entity_mgr.create_entity();
-        entity_mgr.set_use_draw(entity_id);
-        entity_mgr.add_pos_comp(entity_id);
-        entity_mgr.add_sprite_comp(entity_id);
-        entity_mgr.get_sprite_comp(entity_id).tex_name = String::from("assets/tileset.png");
-        entity_mgr.get_sprite_comp(entity_id).tilemap = Some(tilemap);
-        entity_mgr.get_sprite_comp(entity_id).tilemap_width = map_width;
-        entity_mgr.get_sprite_comp(entity_id).tilemap_height = map_height;
-
-        let entity_id = entity_mgr.create_entity();
-        entity_mgr.set_use_draw(entity_id);
-        entity_mgr.set_use_player(entity_id);
-        entity_mgr.add_pos_comp(entity_id);
-        entity_mgr.add_sprite_comp(entity_id);
-        entity_mgr.get_sprite_comp(entity_id).tex_name = String::from("assets/ferris.png");
-        drop(entity_mgr);
+        if let Err(err) = level::spawn_level(&mut entity_mgr, &level, "tilemap") {
+            eprintln!("{}", err);
+            return;
+        }
+        let entity_mgr_mut = sync::Arc::new(sync::Mutex::new(entity_mgr));
+
+        // Register systems
+        // Shared systems declare what they borrow, so non-conflicting ones can run in parallel
+        let mut schedule = schedule::Schedule::new();
+        schedule.add_system(SystemDesc::shared("player", Stage::Update, player_system::update)
+            .reads::<PlayerComponent>()
+            .writes::<PositionComponent>()
+            .reads::<InputManager>()
+            .reads::<LevelBounds>());
+        schedule.add_system(SystemDesc::shared("camera", Stage::Update, camera_system::update)
+            .reads::<PlayerComponent>()
+            .writes::<PositionComponent>()
+            .writes::<Camera>()
+            .reads::<LevelBounds>()
+            .after("player"));
+        schedule.add_system(SystemDesc::new("transform", Stage::PostUpdate, transform_system::update));
+        schedule.add_system(SystemDesc::shared("logging", Stage::PostUpdate, logging_system::update)
+            .writes::<LogComponent>());
+        schedule.add_system(SystemDesc::shared("input", Stage::PostUpdate, input_system::update)
+            .writes::<InputManager>());
+        let mut sprite_quads = draw_system::SpriteQuads::new();
+        schedule.add_system(SystemDesc::shared("draw", Stage::RenderPrep, move |entity_mgr| draw_system::update(entity_mgr, &mut sprite_quads))
+            .writes::<SpriteComponent>()
+            .reads::<PositionComponent>()
+            .reads::<Camera>()
+            .writes::<renderer::Renderer>());
+        schedule.build().expect("Could not order systems.");
 
         // Start game logic thread
         // Runs once per 1/60'th of a second.
         let timer = timer::Timer::new();
         let entity_mgr_mut_ref = sync::Arc::clone(&entity_mgr_mut);
-        let input_mgr_mut_ref = sync::Arc::clone(&input_mgr_mut);
         let guard = timer.schedule_repeating(chrono::Duration::milliseconds(16), move || {
             let mut entity_mgr = entity_mgr_mut_ref.lock().unwrap();
-            let mut input_mgr = input_mgr_mut_ref.lock().unwrap();
-            logging_system::update(&mut entity_mgr);
-            player_system::update(&mut entity_mgr, &input_mgr);
-            input_mgr.update();
+            schedule.run(&mut entity_mgr);
         });
 
         // Start event loop
         let entity_mgr_mut_ref = sync::Arc::clone(&entity_mgr_mut);
-        let input_mgr_mut_ref = sync::Arc::clone(&input_mgr_mut);
         evt_loop.run(move |event, _, control_flow| {
             match event {
                 event::Event::WindowEvent {
@@ -125,7 +129,8 @@ impl Game {
                         input,
                         ..
                     } => {
-                        let mut input_mgr = input_mgr_mut_ref.lock().unwrap();
+                        let entity_mgr = entity_mgr_mut_ref.lock().unwrap();
+                        let mut input_mgr = entity_mgr.res_mut::<input_manager::InputManager>();
 
                         let v_key_code = input.virtual_keycode;
                         if v_key_code.is_none() {
@@ -145,46 +150,13 @@ impl Game {
                     },
                     _ => {}
                 },
-                // If all events were handled, update and render
+                // If all events were handled, render
                 event::Event::MainEventsCleared => {
-                    let mut entity_mgr = entity_mgr_mut_ref.lock().unwrap();
-                    draw_system::update(&mut entity_mgr, &mut renderer);
-                    renderer.render();
+                    let entity_mgr = entity_mgr_mut_ref.lock().unwrap();
+                    entity_mgr.res_mut::<renderer::Renderer>().render();
                 },
                 _ => ()
             }
         });
     }
-}
-
-/// Test if logging works on a single entity.
-#[test]
-fn test_logging_entity() {
-    let mut entity_mgr = entity_manager::EntityManager::new();
-    let entity_id = entity_mgr.create_entity();
-    entity_mgr.add_log_comp(entity_id);
-    entity_mgr.set_use_log(entity_id);
-    
-    let log_comp = entity_mgr.get_log_comp(entity_id);
-    log_comp.message = String::from("Logging test.");
-    log_comp.has_info = true;
-    
-    logging_system::update_entity(log_comp);
-    assert_eq!(log_comp.has_info, false);
-}
-
-/// Test if logging works across the entire entity manager.
-#[test]
-fn test_logging_entities() {
-    let mut entity_mgr = entity_manager::EntityManager::new();
-    let entity_id = entity_mgr.create_entity();
-    entity_mgr.add_log_comp(entity_id);
-    entity_mgr.set_use_log(entity_id);
-
-    let log_comp = entity_mgr.get_log_comp(entity_id);
-    log_comp.message = String::from("Logging test.");
-    log_comp.has_info = true;
-    
-    logging_system::update(&mut entity_mgr);
-    assert_eq!(entity_mgr.get_log_comp(entity_id).has_info, false);
 }
\ No newline at end of file
@@ -87,4 +87,10 @@ impl InputManager {
     pub fn map_key_to_button(&mut self, key: winit::event::VirtualKeyCode, button_code: ButtonCode) {
         self.key_button_map.insert(key, button_code);
     }
+}
+
+impl Default for InputManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,321 @@
+///
+/// Maps level characters to tiles.
+/// A legend lives alongside its level, e.g. level.txt uses level.yaml.
+/// Characters map to a single tile, to rules for horizontal runs, or to
+/// an autotile rule from the tileset metadata named by `tileset`. Characters
+/// are on the terrain layer, unless they name another one.
+/// Spawn markers name the prefab they spawn, and leave `spawn_fill` behind:
+///
+/// tileset: tileset.yaml
+/// tiles:
+///   'x':
+///     autotile: ground
+///   'c':
+///     start: 1
+///     middle: 2
+///     layer: background
+/// spawn_fill: ' '
+/// spawns:
+///   '@': ferris
+///
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::autotile::{AutotileError, AutotileRule, AutotileSet};
+use crate::level::{self, BACKGROUND_LAYER, FOREGROUND_LAYER, TERRAIN_LAYER};
+
+/// Reasons a legend can't be loaded.
+#[derive(Debug, PartialEq)]
+pub enum LegendError {
+    /// The file couldn't be read or parsed.
+    Load(String),
+    /// The tileset's autotile rules couldn't be loaded.
+    Autotile(AutotileError),
+    /// An entry is malformed.
+    BadEntry { key: String, msg: String }
+}
+
+impl fmt::Display for LegendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LegendError::Load(msg) => write!(f, "Could not load legend: {}", msg),
+            LegendError::Autotile(err) => write!(f, "{}", err),
+            LegendError::BadEntry { key, msg } => write!(f, "Legend entry {:?} is invalid: {}", key, msg)
+        }
+    }
+}
+
+/// Which tiles a character uses, depending on its place in a run.
+/// A run is a horizontal line of the same character.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TileRule {
+    /// Used when the character is on its own.
+    pub single: u32,
+    /// Used at the left of a run.
+    pub start: u32,
+    /// Used inside a run.
+    pub middle: u32,
+    /// Used at the right of a run.
+    pub end: u32
+}
+
+impl TileRule {
+    /// Uses the same tile everywhere.
+    pub fn uniform(tile: u32) -> Self {
+        Self {
+            single: tile,
+            start: tile,
+            middle: tile,
+            end: tile
+        }
+    }
+
+    /// Picks a tile from the character's neighbours in its row.
+    pub fn tile(&self, same_left: bool, same_right: bool) -> u32 {
+        match (same_left, same_right) {
+            (false, false) => self.single,
+            (false, true) => self.start,
+            (true, true) => self.middle,
+            (true, false) => self.end
+        }
+    }
+}
+
+/// How a character is turned into a tile.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LegendEntry {
+    /// Picks a tile from its place in a horizontal run.
+    Run(TileRule),
+    /// Picks a tile from neighbours using the same autotile rule.
+    Autotile { name: String, rule: AutotileRule }
+}
+
+/// Maps characters to tile rules.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileLegend {
+    entries: HashMap<char, LegendEntry>,
+    layers: HashMap<char, &'static str>,
+    spawns: HashMap<char, String>,
+    spawn_fill: Option<char>
+}
+
+impl TileLegend {
+    /// Loads a legend from a YAML file.
+    /// The tileset it names is found relative to the legend.
+    pub fn load(path: &str) -> Result<Self, LegendError> {
+        let contents = std::fs::read_to_string(path).map_err(|err| LegendError::Load(format!("{}: {}", path, err)))?;
+        let docs = YamlLoader::load_from_str(&contents).map_err(|err| LegendError::Load(err.to_string()))?;
+        let autotiles = match docs.first().and_then(|doc| doc["tileset"].as_str()) {
+            Some(tileset) => {
+                let tileset_path = Path::new(path).with_file_name(tileset);
+                AutotileSet::load(&tileset_path.to_string_lossy()).map_err(LegendError::Autotile)?
+            },
+            None => AutotileSet::empty()
+        };
+        Self::parse(&contents, &autotiles)
+    }
+
+    /// Parses a legend from a YAML string.
+    /// Autotile entries are looked up in the given rules.
+    pub fn parse(contents: &str, autotiles: &AutotileSet) -> Result<Self, LegendError> {
+        let docs = YamlLoader::load_from_str(contents).map_err(|err| LegendError::Load(err.to_string()))?;
+        let tiles = match docs.first().map(|doc| &doc["tiles"]) {
+            Some(Yaml::Hash(tiles)) => tiles,
+            _ => return Err(LegendError::Load(String::from("Legend needs a \"tiles\" map.")))
+        };
+
+        let mut entries = HashMap::new();
+        let mut layers = HashMap::new();
+        for (key, value) in tiles {
+            let key = yaml_key(key);
+            let c = key_char(&key)?;
+            let entry = match value["autotile"].as_str() {
+                Some(name) => match autotiles.rule(name) {
+                    Some(rule) => LegendEntry::Autotile {
+                        name: String::from(name),
+                        rule: rule.clone()
+                    },
+                    None => return Err(bad_entry(&key, &format!("the tileset has no autotile \"{}\".", name)))
+                },
+                None => LegendEntry::Run(parse_rule(&key, value)?)
+            };
+            let layer = match &value["layer"] {
+                Yaml::BadValue => TERRAIN_LAYER,
+                layer => match layer.as_str() {
+                    Some(BACKGROUND_LAYER) => BACKGROUND_LAYER,
+                    Some(TERRAIN_LAYER) => TERRAIN_LAYER,
+                    Some(FOREGROUND_LAYER) => FOREGROUND_LAYER,
+                    _ => return Err(bad_entry(&key, "\"layer\" should be background, terrain or foreground."))
+                }
+            };
+            entries.insert(c, entry);
+            layers.insert(c, layer);
+        }
+
+        // Spawn markers
+        let mut spawns = HashMap::new();
+        match &docs[0]["spawns"] {
+            Yaml::Hash(markers) => for (key, prefab) in markers {
+                let key = yaml_key(key);
+                let c = key_char(&key)?;
+                if entries.contains_key(&c) {
+                    return Err(bad_entry(&key, "spawn markers can't also be tiles."));
+                }
+                let prefab = prefab.as_str().ok_or_else(|| bad_entry(&key, "spawn markers should name a prefab."))?;
+                spawns.insert(c, String::from(prefab));
+            },
+            Yaml::BadValue => {},
+            _ => return Err(LegendError::Load(String::from("\"spawns\" should map characters to prefabs.")))
+        }
+        let spawn_fill = match &docs[0]["spawn_fill"] {
+            Yaml::BadValue => None,
+            fill => {
+                let key = yaml_key(fill);
+                let c = key_char(&key)?;
+                if !entries.contains_key(&c) {
+                    return Err(bad_entry(&key, "the spawn fill should be a tile."));
+                }
+                Some(c)
+            }
+        };
+
+        Ok(Self {
+            entries,
+            layers,
+            spawns,
+            spawn_fill
+        })
+    }
+
+    /// Returns the entry for a character, if it has one.
+    pub fn entry(&self, c: char) -> Option<&LegendEntry> {
+        self.entries.get(&c)
+    }
+
+    /// Returns the prefab a spawn marker spawns, if the character is one.
+    pub fn spawn(&self, c: char) -> Option<&str> {
+        self.spawns.get(&c).map(|prefab| prefab.as_str())
+    }
+
+    /// Returns the character drawn under spawn markers, if there is one.
+    pub fn spawn_fill(&self) -> Option<char> {
+        self.spawn_fill
+    }
+
+    /// Returns the layer a character is on.
+    pub fn layer(&self, c: char) -> &'static str {
+        self.layers.get(&c).copied().unwrap_or(TERRAIN_LAYER)
+    }
+}
+
+/// Parses a tile index, or a map of run rules.
+/// Missing run tiles fall back to the start tile, and the end tile to the middle one.
+fn parse_rule(key: &str, value: &Yaml) -> Result<TileRule, LegendError> {
+    match value {
+        Yaml::Integer(tile) => level::to_tile(*tile)
+            .map(TileRule::uniform)
+            .ok_or_else(|| bad_entry(key, &format!("{} is not a tile index.", tile))),
+        Yaml::Hash(fields) => {
+            for field in fields.keys() {
+                let field = yaml_key(field);
+                if !["single", "start", "middle", "end", "layer"].contains(&field.as_str()) {
+                    return Err(bad_entry(key, &format!("unknown field \"{}\".", field)));
+                }
+            }
+
+            let read = |field: &str| -> Result<Option<u32>, LegendError> {
+                match &value[field] {
+                    Yaml::BadValue => Ok(None),
+                    Yaml::Integer(tile) => level::to_tile(*tile)
+                        .map(Some)
+                        .ok_or_else(|| bad_entry(key, &format!("{} is not a tile index.", tile))),
+                    _ => Err(bad_entry(key, &format!("\"{}\" should be a tile index.", field)))
+                }
+            };
+            let start = read("start")?.ok_or_else(|| bad_entry(key, "runs need a start tile."))?;
+            let middle = read("middle")?.unwrap_or(start);
+            Ok(TileRule {
+                single: read("single")?.unwrap_or(start),
+                start,
+                middle,
+                end: read("end")?.unwrap_or(middle)
+            })
+        },
+        _ => Err(bad_entry(key, "should be a tile index or run rules."))
+    }
+}
+
+/// Returns the character a key is for.
+fn key_char(key: &str) -> Result<char, LegendError> {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(bad_entry(key, "keys should be a single character."))
+    }
+}
+
+/// Returns a YAML key as a string.
+/// Numbers are allowed, since digits make fine level characters.
+fn yaml_key(key: &Yaml) -> String {
+    match key {
+        Yaml::String(key) => key.clone(),
+        Yaml::Integer(key) => key.to_string(),
+        _ => String::new()
+    }
+}
+
+/// Creates a BadEntry error.
+fn bad_entry(key: &str, msg: &str) -> LegendError {
+    LegendError::BadEntry {
+        key: String::from(key),
+        msg: String::from(msg)
+    }
+}
+
+/// Test if runs pick their start, middle and end tiles.
+#[test]
+fn test_legend_runs() {
+    let run_rule = |legend: &TileLegend, c| match legend.entry(c) {
+        Some(LegendEntry::Run(rule)) => *rule,
+        entry => panic!("{:?} is not a run.", entry)
+    };
+
+    let autotiles = AutotileSet::parse("autotiles:\n  ground:\n    neighbours: 4\n    default: 4\n").unwrap();
+    let legend = TileLegend::parse("
+tiles:
+  'x': 4
+  'p':
+    single: 9
+    start: 1
+    middle: 2
+    end: 3
+  'c':
+    start: 1
+    middle: 2
+  '=':
+    autotile: ground
+    layer: foreground
+", &autotiles).unwrap();
+    assert_eq!(run_rule(&legend, 'x'), TileRule::uniform(4));
+    assert!(matches!(legend.entry('='), Some(LegendEntry::Autotile { .. })));
+    assert_eq!((legend.layer('x'), legend.layer('=')), (TERRAIN_LAYER, FOREGROUND_LAYER));
+
+    let pipe = run_rule(&legend, 'p');
+    assert_eq!([pipe.tile(false, false), pipe.tile(false, true), pipe.tile(true, true), pipe.tile(true, false)], [9, 1, 2, 3]);
+
+    // Missing tiles fall back
+    let cloud = run_rule(&legend, 'c');
+    assert_eq!([cloud.single, cloud.start, cloud.middle, cloud.end], [1, 1, 2, 2]);
+
+    assert!(matches!(TileLegend::parse("tiles:\n  'xy': 4\n", &autotiles), Err(LegendError::BadEntry { .. })));
+    assert!(matches!(TileLegend::parse("tiles:\n  'x': 4294967295\n", &autotiles), Err(LegendError::BadEntry { .. })));
+    assert!(matches!(TileLegend::parse("tiles:\n  'x':\n    middle: 4\n", &autotiles), Err(LegendError::BadEntry { .. })));
+    assert!(matches!(TileLegend::parse("tiles:\n  'x':\n    autotile: water\n", &autotiles), Err(LegendError::BadEntry { .. })));
+    assert!(matches!(TileLegend::parse("tiles:\n  'x':\n    start: 4\n    layer: sky\n", &autotiles), Err(LegendError::BadEntry { .. })));
+    assert!(matches!(TileLegend::parse("tiles:\n  'x': 4\nspawns:\n  'x': ferris\n", &autotiles), Err(LegendError::BadEntry { .. })));
+    assert!(matches!(TileLegend::parse("tiles:\n  'x': 4\nspawn_fill: 'y'\n", &autotiles), Err(LegendError::BadEntry { .. })));
+}
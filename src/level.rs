@@ -0,0 +1,475 @@
+///
+/// Loads levels from ASCII files.
+/// Each line is a row of tiles, and every row must end with a newline.
+/// Characters are turned into tiles by the level's legend, which also puts
+/// them on the background, terrain or foreground layer. The terrain is solid,
+/// so it's copied to the collision layer.
+/// Spawn markers in the legend are taken out of the tiles, and become spawn points.
+/// Maps made with Tiled are imported instead, see `tiled`.
+///
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::components::SpriteComponent;
+use crate::entity_manager::{EntityId, EntityManager};
+use crate::legend::{LegendEntry, LegendError, TileLegend};
+use crate::prefab::PrefabError;
+use crate::tiled::{self, TiledError};
+
+/// Marks a cell with no tile.
+pub const EMPTY_TILE: u32 = u32::MAX;
+
+/// Size of a tile in pixels, as in the tileset.
+/// ASCII levels use it, and Tiled maps bring their own.
+pub const TILE_SIZE: u32 = 16;
+
+pub const BACKGROUND_LAYER: &str = "background";
+pub const TERRAIN_LAYER: &str = "terrain";
+pub const FOREGROUND_LAYER: &str = "foreground";
+pub const COLLISION_LAYER: &str = "collision";
+
+/// Draw orders of the standard layers.
+/// Entities between terrain and foreground are drawn in front of the level, but behind overlays.
+pub const BACKGROUND_DRAW_ORDER: i32 = -10;
+pub const TERRAIN_DRAW_ORDER: i32 = 0;
+pub const FOREGROUND_DRAW_ORDER: i32 = 10;
+
+/// Returns the draw order for a layer name.
+/// The collision layer isn't drawn, and other names are drawn with the terrain.
+pub fn default_draw_order(name: &str) -> Option<i32> {
+    match name {
+        BACKGROUND_LAYER => Some(BACKGROUND_DRAW_ORDER),
+        FOREGROUND_LAYER => Some(FOREGROUND_DRAW_ORDER),
+        COLLISION_LAYER => None,
+        _ => Some(TERRAIN_DRAW_ORDER)
+    }
+}
+
+/// Returns true if a tilemap holds exactly width * height tiles.
+pub fn tilemap_fits(tilemap: &[u32], width: u32, height: u32) -> bool {
+    tilemap.len() as u64 == width as u64 * height as u64
+}
+
+/// Converts a tile index read from a file, if it's in range.
+/// EMPTY_TILE marks cells with no tile, so it isn't a tile index.
+pub fn to_tile(tile: i64) -> Option<u32> {
+    if tile < 0 || tile >= EMPTY_TILE as i64 {
+        return None;
+    }
+    Some(tile as u32)
+}
+
+/// Reasons a level can't be loaded.
+/// Lines and columns start at 1.
+#[derive(Debug, PartialEq)]
+pub enum LevelError {
+    /// The file couldn't be read.
+    Io(String),
+    /// The level's legend couldn't be loaded.
+    Legend(LegendError),
+    /// A Tiled map couldn't be imported.
+    Tiled(TiledError),
+    /// The level has no rows, or its rows have no tiles.
+    Empty,
+    /// A row is a different width from the first.
+    RaggedRow { line: usize, width: usize, expected: usize },
+    /// A character doesn't map to a tile.
+    UnknownChar { line: usize, column: usize, c: char },
+    /// The last row doesn't end with a newline.
+    MissingTrailingNewline { line: usize }
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io(msg) => write!(f, "Could not read level: {}", msg),
+            LevelError::Legend(err) => write!(f, "{}", err),
+            LevelError::Tiled(err) => write!(f, "{}", err),
+            LevelError::Empty => write!(f, "Level has no tiles."),
+            LevelError::RaggedRow { line, width, expected } => write!(f, "Line {}: row is {} tiles wide, expected {}.", line, width, expected),
+            LevelError::UnknownChar { line, column, c } => write!(f, "Line {}, column {}: unknown tile {:?}.", line, column, c),
+            LevelError::MissingTrailingNewline { line } => write!(f, "Line {}: last row should end with a newline.", line)
+        }
+    }
+}
+
+/// A grid of tile indices.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileLayer {
+    pub name: String,
+    pub tiles: Vec<u32>,
+    /// Layers without a draw order aren't drawn.
+    pub draw_order: Option<i32>
+}
+
+/// Where an entity should be created, in tiles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnPoint {
+    /// The prefab to spawn.
+    pub name: String,
+    pub x: u32,
+    pub y: u32
+}
+
+/// Gameplay properties of a tile.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct TileProperties {
+    /// Blocks movement from every side.
+    pub solid: bool,
+    /// Blocks movement from above only.
+    pub one_way: bool,
+    /// Hurts on touch.
+    pub hazard: bool
+}
+
+/// Edges of a level, in pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LevelBounds {
+    pub width: f32,
+    pub height: f32,
+    /// Top of the solid rows along the bottom of the level, or its bottom if there are none.
+    pub floor: f32
+}
+
+/// A loaded level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    pub width: u32,
+    pub height: u32,
+    /// Size of a tile in pixels.
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub layers: Vec<TileLayer>,
+    pub spawns: Vec<SpawnPoint>,
+    /// Properties by tile index. Tiles without an entry have none set.
+    pub tile_properties: HashMap<u32, TileProperties>
+}
+
+impl Level {
+    /// Loads a level from a file.
+    /// Tiled maps are .tmx or .json files, anything else is ASCII and its legend is the YAML file with the same name.
+    pub fn load(path: &str) -> Result<Self, LevelError> {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("tmx") | Some("json") => return tiled::load(path).map_err(LevelError::Tiled),
+            _ => {}
+        }
+
+        let contents = std::fs::read_to_string(path).map_err(|err| LevelError::Io(format!("{}: {}", path, err)))?;
+        let legend_path = Path::new(path).with_extension("yaml");
+        let legend = TileLegend::load(&legend_path.to_string_lossy()).map_err(LevelError::Legend)?;
+        Self::parse(&contents, &legend)
+    }
+
+    /// Parses a level from a string, using a legend for its tiles.
+    pub fn parse(contents: &str, legend: &TileLegend) -> Result<Self, LevelError> {
+        // Spawn markers are replaced by the legend's fill, or left empty
+        let mut rows: Vec<Vec<Option<char>>> = Vec::new();
+        let mut spawns = Vec::new();
+        let mut width = 0;
+        let mut height = 0;
+        for (line_index, row) in contents.split_terminator('\n').enumerate() {
+            let line = line_index + 1;
+            let row = row.strip_suffix('\r').unwrap_or(row);
+            let row_width = row.chars().count();
+            if line == 1 {
+                width = row_width;
+            }
+            else if row_width != width {
+                return Err(LevelError::RaggedRow {
+                    line,
+                    width: row_width,
+                    expected: width
+                });
+            }
+
+            let mut chars = Vec::with_capacity(width);
+            for (column_index, c) in row.chars().enumerate() {
+                if let Some(prefab) = legend.spawn(c) {
+                    spawns.push(SpawnPoint {
+                        name: String::from(prefab),
+                        x: column_index as u32,
+                        y: line_index as u32
+                    });
+                    chars.push(legend.spawn_fill());
+                }
+                else if legend.entry(c).is_some() {
+                    chars.push(Some(c));
+                }
+                else {
+                    return Err(LevelError::UnknownChar {
+                        line,
+                        column: column_index + 1,
+                        c
+                    });
+                }
+            }
+            rows.push(chars);
+            height = line;
+        }
+
+        if width == 0 || height == 0 {
+            return Err(LevelError::Empty);
+        }
+        if !contents.ends_with('\n') {
+            return Err(LevelError::MissingTrailingNewline { line: height });
+        }
+
+        // Autotiles look at the rows above and below, so tiles are picked once every row is known
+        let layer_names = [BACKGROUND_LAYER, TERRAIN_LAYER, FOREGROUND_LAYER];
+        let mut layer_tiles = vec![vec![EMPTY_TILE; width * height]; layer_names.len()];
+        for (y, chars) in rows.iter().enumerate() {
+            for (x, &c) in chars.iter().enumerate() {
+                let c = match c {
+                    Some(c) => c,
+                    None => continue
+                };
+                let tile = match legend.entry(c) {
+                    // Runs only continue along a row
+                    Some(LegendEntry::Run(rule)) => {
+                        let same_left = x > 0 && chars[x - 1] == Some(c);
+                        let same_right = chars.get(x + 1) == Some(&Some(c));
+                        rule.tile(same_left, same_right)
+                    },
+                    // Neighbours connect if they use the same autotile, and the level's edges always connect
+                    Some(LegendEntry::Autotile { name, rule }) => rule.tile(|dx, dy| {
+                        let nx = x as i32 + dx;
+                        let ny = y as i32 + dy;
+                        if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                            return true;
+                        }
+                        match rows[ny as usize][nx as usize].and_then(|c| legend.entry(c)) {
+                            Some(LegendEntry::Autotile { name: other, .. }) => other == name,
+                            _ => false
+                        }
+                    }),
+                    None => unreachable!()
+                };
+                let layer_index = layer_names.iter().position(|&name| name == legend.layer(c)).unwrap_or(1);
+                layer_tiles[layer_index][y * width + x] = tile;
+            }
+        }
+
+        // Empty layers aren't kept, except the terrain that collision copies
+        let mut layers = Vec::new();
+        for (&name, tiles) in layer_names.iter().zip(layer_tiles) {
+            if name == TERRAIN_LAYER || tiles.iter().any(|&tile| tile != EMPTY_TILE) {
+                layers.push(TileLayer {
+                    name: String::from(name),
+                    tiles,
+                    draw_order: default_draw_order(name)
+                });
+            }
+        }
+        let terrain = layers.iter().find(|layer| layer.name == TERRAIN_LAYER).map(|layer| layer.tiles.clone()).unwrap_or_default();
+        let mut tile_properties = HashMap::new();
+        for &tile in terrain.iter().filter(|&&tile| tile != EMPTY_TILE) {
+            tile_properties.insert(tile, TileProperties { solid: true, ..TileProperties::default() });
+        }
+        layers.push(TileLayer {
+            name: String::from(COLLISION_LAYER),
+            tiles: terrain,
+            draw_order: default_draw_order(COLLISION_LAYER)
+        });
+
+        Ok(Self {
+            width: width as u32,
+            height: height as u32,
+            tile_width: TILE_SIZE,
+            tile_height: TILE_SIZE,
+            layers,
+            spawns,
+            tile_properties
+        })
+    }
+
+    /// Returns the layer with a name, if there is one.
+    pub fn layer(&self, name: &str) -> Option<&TileLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    /// Returns true if the collision layer has a solid tile at (x, y).
+    /// Levels without a collision layer collide with their terrain, and other layers never collide.
+    pub fn is_solid(&self, x: u32, y: u32) -> bool {
+        let index = (y * self.width + x) as usize;
+        let layer = self.layer(COLLISION_LAYER).or_else(|| self.layer(TERRAIN_LAYER));
+        matches!(layer.and_then(|layer| layer.tiles.get(index)), Some(&tile) if tile != EMPTY_TILE && self.properties(tile).solid)
+    }
+
+    /// Returns the level's edges.
+    pub fn bounds(&self) -> LevelBounds {
+        let solid_rows = (0..self.height).rev()
+            .take_while(|&y| (0..self.width).all(|x| self.is_solid(x, y)))
+            .count() as u32;
+        LevelBounds {
+            width: (self.width * self.tile_width) as f32,
+            height: (self.height * self.tile_height) as f32,
+            floor: ((self.height - solid_rows) * self.tile_height) as f32
+        }
+    }
+
+    /// Returns the properties of a tile index.
+    pub fn properties(&self, tile: u32) -> TileProperties {
+        self.tile_properties.get(&tile).copied().unwrap_or_default()
+    }
+}
+
+/// Creates the entities for a level.
+/// Each drawn layer is a tilemap prefab, and each spawn point is its prefab, placed at its tile.
+/// If any prefab fails to spawn, the entities spawned so far are destroyed.
+pub fn spawn_level(entity_mgr: &mut EntityManager, level: &Level, tilemap_prefab: &str) -> Result<Vec<EntityId>, PrefabError> {
+    let mut entity_ids = Vec::new();
+    if let Err(err) = spawn_entities(entity_mgr, level, tilemap_prefab, &mut entity_ids) {
+        for entity_id in entity_ids {
+            entity_mgr.destroy_entity(entity_id);
+        }
+        return Err(err);
+    }
+    Ok(entity_ids)
+}
+
+/// Spawns a level's entities, collecting their IDs as it goes.
+fn spawn_entities(entity_mgr: &mut EntityManager, level: &Level, tilemap_prefab: &str, entity_ids: &mut Vec<EntityId>) -> Result<(), PrefabError> {
+    for layer in &level.layers {
+        let draw_order = match layer.draw_order {
+            Some(draw_order) => draw_order,
+            None => continue
+        };
+        let entity_id = entity_mgr.spawn_prefab(tilemap_prefab, 0, 0)?;
+        entity_ids.push(entity_id);
+        if let Ok(sprite_comp) = entity_mgr.try_get_mut::<SpriteComponent>(entity_id) {
+            sprite_comp.tilemap = Some(layer.tiles.clone());
+            sprite_comp.tilemap_width = level.width;
+            sprite_comp.tilemap_height = level.height;
+            sprite_comp.tile_width = level.tile_width;
+            sprite_comp.tile_height = level.tile_height;
+            sprite_comp.draw_order = draw_order;
+        }
+    }
+
+    for spawn in &level.spawns {
+        entity_ids.push(entity_mgr.spawn_prefab(&spawn.name, (spawn.x * level.tile_width) as i32, (spawn.y * level.tile_height) as i32)?);
+    }
+    Ok(())
+}
+
+/// Creates a legend for tests.
+#[cfg(test)]
+fn test_legend() -> TileLegend {
+    let autotiles = crate::autotile::AutotileSet::parse("
+autotiles:
+  ground:
+    neighbours: 4
+    default: 9
+    tiles:
+      14: 6
+      15: 7
+").unwrap();
+    TileLegend::parse("
+tiles:
+  'x': 4
+  ' ': 5
+  'c':
+    start: 1
+    middle: 2
+  '=':
+    autotile: ground
+  'b':
+    start: 3
+    layer: background
+  'f':
+    start: 8
+    layer: foreground
+spawn_fill: ' '
+spawns:
+  '@': ferris
+  'o': coin
+", &autotiles).unwrap()
+}
+
+/// Test if a level parses into tiles.
+#[test]
+fn test_parse_level() {
+    let level = Level::parse("cc \nxxx\n", &test_legend()).unwrap();
+    assert_eq!(level.width, 3);
+    assert_eq!(level.height, 2);
+    assert_eq!(level.layers[0].tiles, vec![1, 2, 5, 4, 4, 4]);
+}
+
+/// Test if characters go on their layers, and terrain is copied to collision.
+#[test]
+fn test_parse_layers() {
+    let level = Level::parse("bf\nxx\n", &test_legend()).unwrap();
+    let names: Vec<_> = level.layers.iter().map(|layer| (layer.name.as_str(), layer.draw_order)).collect();
+    assert_eq!(names, vec![
+        (BACKGROUND_LAYER, Some(BACKGROUND_DRAW_ORDER)),
+        (TERRAIN_LAYER, Some(TERRAIN_DRAW_ORDER)),
+        (FOREGROUND_LAYER, Some(FOREGROUND_DRAW_ORDER)),
+        (COLLISION_LAYER, None)
+    ]);
+    assert_eq!(level.layer(BACKGROUND_LAYER).unwrap().tiles, vec![3, EMPTY_TILE, EMPTY_TILE, EMPTY_TILE]);
+    assert_eq!(level.layer(FOREGROUND_LAYER).unwrap().tiles, vec![EMPTY_TILE, 8, EMPTY_TILE, EMPTY_TILE]);
+    assert_eq!(level.layer(COLLISION_LAYER).unwrap().tiles, vec![EMPTY_TILE, EMPTY_TILE, 4, 4]);
+    assert!(level.properties(4).solid);
+    assert!(!level.properties(3).solid);
+}
+
+/// Test if autotiles pick tiles from neighbours in every direction.
+#[test]
+fn test_parse_autotiles() {
+    // The level's edges connect, other tiles don't
+    let level = Level::parse("x x\n=x=\n===\n", &test_legend()).unwrap();
+    assert_eq!(level.layers[0].tiles, vec![4, 5, 4, 9, 4, 9, 7, 6, 7]);
+}
+
+/// Test if spawn markers become spawn points, with the fill tile left behind.
+#[test]
+fn test_parse_spawns() {
+    let level = Level::parse("@ o\nxxx\n", &test_legend()).unwrap();
+    assert_eq!(level.layers[0].tiles[0..3], [5, 5, 5]);
+    assert_eq!(level.spawns, vec![
+        SpawnPoint { name: String::from("ferris"), x: 0, y: 0 },
+        SpawnPoint { name: String::from("coin"), x: 2, y: 0 }
+    ]);
+}
+
+/// Test if bounds come from the level's size and its solid bottom rows.
+#[test]
+fn test_level_bounds() {
+    let legend = test_legend();
+    let level = Level::parse("bbbx\nxxxx\nxxxx\n", &legend).unwrap();
+    assert_eq!(level.bounds(), LevelBounds { width: 64.0, height: 48.0, floor: 16.0 });
+
+    // Without a solid bottom row, the floor is the bottom of the level
+    let level = Level::parse("xxxx\nxxbx\n", &legend).unwrap();
+    assert_eq!(level.bounds().floor, 32.0);
+
+    // Background tiles don't collide, even if terrain uses the same tile
+    let legend = TileLegend::parse("tiles:\n  'x': 4\n  'd':\n    start: 4\n    layer: background\n", &crate::autotile::AutotileSet::empty()).unwrap();
+    let level = Level::parse("dd\nxx\n", &legend).unwrap();
+    assert!(level.properties(4).solid);
+    assert!(!level.is_solid(0, 0));
+    assert_eq!(level.bounds().floor, 16.0);
+}
+
+/// Test if tile indices are range checked, leaving room for EMPTY_TILE.
+#[test]
+fn test_to_tile() {
+    assert_eq!(to_tile(0), Some(0));
+    assert_eq!(to_tile(EMPTY_TILE as i64 - 1), Some(EMPTY_TILE - 1));
+    assert_eq!(to_tile(EMPTY_TILE as i64), None);
+    assert_eq!(to_tile(-1), None);
+}
+
+/// Test if malformed levels report where they went wrong.
+#[test]
+fn test_level_errors() {
+    let legend = test_legend();
+    assert_eq!(Level::parse("", &legend), Err(LevelError::Empty));
+    assert_eq!(Level::parse("\n", &legend), Err(LevelError::Empty));
+    assert_eq!(Level::parse("\n\n", &legend), Err(LevelError::Empty));
+    assert_eq!(Level::parse("xx\nx\n", &legend), Err(LevelError::RaggedRow { line: 2, width: 1, expected: 2 }));
+    assert_eq!(Level::parse("xx\nx?\n", &legend), Err(LevelError::UnknownChar { line: 2, column: 2, c: '?' }));
+    assert_eq!(Level::parse("xx\nxx", &legend), Err(LevelError::MissingTrailingNewline { line: 2 }));
+}
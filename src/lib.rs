@@ -0,0 +1,24 @@
+///
+/// Engine for Super Ferris.
+/// Entities, components, systems, and the renderer they draw with.
+/// 
+
+pub mod entity_manager;
+pub mod component_list;
+pub mod borrow_cell;
+pub mod query;
+pub mod schedule;
+pub mod events;
+pub mod commands;
+pub mod snapshot;
+pub mod prefab;
+pub mod level;
+pub mod legend;
+pub mod autotile;
+pub mod tiled;
+pub mod camera;
+pub mod renderer;
+pub mod texture;
+pub mod input_manager;
+pub mod components;
+pub mod systems;
\ No newline at end of file
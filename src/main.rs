@@ -2,15 +2,15 @@
 /// Entry point for Super Ferris.
 /// 
 
+use std::env;
+
 mod game;
-mod entity_manager;
-mod renderer;
-mod texture;
-mod input_manager;
-mod components;
-mod systems;
+
+const DEFAULT_LEVEL: &str = "assets/level.txt";
 
 fn main() {
-    let mut my_game = game::Game::new();
+    // The level can be passed as the first argument
+    let level_path = env::args().nth(1).unwrap_or_else(|| String::from(DEFAULT_LEVEL));
+    let mut my_game = game::Game::new(&level_path);
     my_game.run();
-}
+}
\ No newline at end of file
@@ -0,0 +1,145 @@
+///
+/// Entity prefabs loaded from YAML.
+/// Each prefab maps component names to the fields to set on them, e.g.
+///
+/// ferris:
+///   player:
+///   position:
+///   sprite:
+///     tex_name: assets/ferris.png
+///
+
+use std::collections::HashMap;
+use std::fmt;
+
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::components::Component;
+
+/// Reasons a prefab can't be loaded or spawned.
+#[derive(Debug, PartialEq)]
+pub enum PrefabError {
+    /// The prefab file couldn't be read or parsed.
+    Load(String),
+    /// No prefab has this name.
+    UnknownPrefab(String),
+    /// A prefab uses a component that wasn't registered.
+    UnknownComponent { prefab: String, component: String },
+    /// A component has a field it doesn't know about.
+    UnknownField { component: String, field: String },
+    /// A field has the wrong type of value.
+    BadField { field: String, expected: &'static str }
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrefabError::Load(msg) => write!(f, "Could not load prefabs: {}", msg),
+            PrefabError::UnknownPrefab(name) => write!(f, "No prefab named \"{}\".", name),
+            PrefabError::UnknownComponent { prefab, component } => write!(f, "Prefab \"{}\" uses unknown component \"{}\".", prefab, component),
+            PrefabError::UnknownField { component, field } => write!(f, "Component \"{}\" has no field \"{}\".", component, field),
+            PrefabError::BadField { field, expected } => write!(f, "Field \"{}\" should be {}.", field, expected)
+        }
+    }
+}
+
+/// A component that can be set up from prefab fields.
+pub trait FromPrefab: Component {
+    /// Names of the fields that can be set.
+    const FIELDS: &'static [&'static str];
+
+    /// Sets fields from a YAML map.
+    /// Fields that aren't listed keep their uninit values.
+    fn apply_fields(&mut self, fields: &Yaml) -> Result<(), PrefabError>;
+}
+
+/// Holds prefab definitions by name.
+pub struct PrefabLibrary {
+    prefabs: HashMap<String, Yaml>
+}
+
+impl PrefabLibrary {
+    /// Loads prefabs from a YAML file.
+    pub fn load(path: &str) -> Result<Self, PrefabError> {
+        let contents = std::fs::read_to_string(path).map_err(|err| PrefabError::Load(format!("{}: {}", path, err)))?;
+        Self::parse(&contents)
+    }
+
+    /// Parses prefabs from a YAML string.
+    pub fn parse(contents: &str) -> Result<Self, PrefabError> {
+        let docs = YamlLoader::load_from_str(contents).map_err(|err| PrefabError::Load(err.to_string()))?;
+        let mut prefabs = HashMap::new();
+        if let Some(doc) = docs.first() {
+            let doc = doc.as_hash().ok_or_else(|| PrefabError::Load(String::from("Top level should map names to prefabs.")))?;
+            for (name, prefab) in doc {
+                let name = name.as_str().ok_or_else(|| PrefabError::Load(String::from("Prefab names should be strings.")))?;
+                match prefab {
+                    Yaml::Hash(_) | Yaml::Null => {},
+                    _ => return Err(PrefabError::Load(format!("Prefab \"{}\" should map component names to fields.", name)))
+                }
+                prefabs.insert(String::from(name), prefab.clone());
+            }
+        }
+
+        Ok(Self {
+            prefabs
+        })
+    }
+
+    /// Returns a prefab's definition.
+    pub fn get(&self, name: &str) -> Result<&Yaml, PrefabError> {
+        self.prefabs.get(name).ok_or_else(|| PrefabError::UnknownPrefab(String::from(name)))
+    }
+}
+
+/// Errors if any field isn't one the component knows about.
+pub fn check_fields(component: &str, fields: &Yaml, known: &[&str]) -> Result<(), PrefabError> {
+    if let Some(fields) = fields.as_hash() {
+        for field in fields.keys() {
+            let field = field.as_str().unwrap_or("");
+            if !known.contains(&field) {
+                return Err(PrefabError::UnknownField {
+                    component: String::from(component),
+                    field: String::from(field)
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads an integer field, if present.
+pub fn read_int(fields: &Yaml, field: &str) -> Result<Option<i64>, PrefabError> {
+    match &fields[field] {
+        Yaml::BadValue => Ok(None),
+        Yaml::Integer(value) => Ok(Some(*value)),
+        _ => Err(bad_field(field, "an integer"))
+    }
+}
+
+/// Reads a number field, if present.
+pub fn read_float(fields: &Yaml, field: &str) -> Result<Option<f32>, PrefabError> {
+    match &fields[field] {
+        Yaml::BadValue => Ok(None),
+        Yaml::Integer(value) => Ok(Some(*value as f32)),
+        Yaml::Real(_) => Ok(fields[field].as_f64().map(|value| value as f32)),
+        _ => Err(bad_field(field, "a number"))
+    }
+}
+
+/// Reads a string field, if present.
+pub fn read_string(fields: &Yaml, field: &str) -> Result<Option<String>, PrefabError> {
+    match &fields[field] {
+        Yaml::BadValue => Ok(None),
+        Yaml::String(value) => Ok(Some(value.clone())),
+        _ => Err(bad_field(field, "a string"))
+    }
+}
+
+/// Creates a BadField error.
+fn bad_field(field: &str, expected: &'static str) -> PrefabError {
+    PrefabError::BadField {
+        field: String::from(field),
+        expected
+    }
+}
@@ -0,0 +1,406 @@
+///
+/// Iterates over entities that have a set of components.
+/// Component lists are borrowed for as long as the query lives,
+/// so conflicting borrows panic instead of aliasing.
+///
+
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use crate::borrow_cell::{Ref, RefMut};
+use crate::component_list::{ComponentList, ComponentTicks};
+use crate::components::Component;
+use crate::entity_manager::{Entity, EntityId, EntityManager};
+
+/// Something that can be fetched for each matching entity.
+/// Implemented for `&T`, `&mut T`, `EntityId`, `Added`, `Changed` and tuples of those.
+pub trait Query {
+    /// Borrowed component lists.
+    type Borrow<'w>;
+    /// What is yielded per entity.
+    type Item<'q>;
+
+    /// Borrows the component lists needed by the query.
+    fn borrow(entity_mgr: &EntityManager) -> Self::Borrow<'_>;
+
+    /// Returns true if the entity matches the query.
+    fn matches(borrow: &Self::Borrow<'_>, entity_index: u32) -> bool;
+
+    /// Returns the entities that might match, if the query can narrow them down.
+    /// Queries on components use the entities in the component list.
+    fn candidates<'q>(borrow: &'q Self::Borrow<'_>) -> Option<&'q [u32]>;
+
+    /// Fetches the entity's components.
+    ///
+    /// # Safety
+    /// Each entity may only be fetched once while its items are alive.
+    unsafe fn fetch<'q>(borrow: &'q Self::Borrow<'_>, entity_index: u32) -> Self::Item<'q>;
+}
+
+impl Query for EntityId {
+    type Borrow<'w> = &'w [Entity];
+    type Item<'q> = EntityId;
+
+    fn borrow(entity_mgr: &EntityManager) -> Self::Borrow<'_> {
+        entity_mgr.entities.as_slice()
+    }
+
+    fn matches(_borrow: &Self::Borrow<'_>, _entity_index: u32) -> bool {
+        true
+    }
+
+    fn candidates<'q>(_borrow: &'q Self::Borrow<'_>) -> Option<&'q [u32]> {
+        None
+    }
+
+    unsafe fn fetch<'q>(borrow: &'q Self::Borrow<'_>, entity_index: u32) -> Self::Item<'q> {
+        borrow[entity_index as usize].id
+    }
+}
+
+/// A query on a single component, whose change ticks can be checked.
+pub trait ComponentQuery: Query {
+    /// Returns the ticks of a matching entity's component.
+    fn ticks(borrow: &Self::Borrow<'_>, entity_index: u32) -> ComponentTicks;
+}
+
+impl<T: Component> Query for &T {
+    type Borrow<'w> = Option<Ref<'w, ComponentList<T>>>;
+    type Item<'q> = &'q T;
+
+    fn borrow(entity_mgr: &EntityManager) -> Self::Borrow<'_> {
+        entity_mgr.borrow_comp_list::<T>()
+    }
+
+    fn matches(borrow: &Self::Borrow<'_>, entity_index: u32) -> bool {
+        match borrow {
+            Some(list) => list.has(entity_index),
+            None => false
+        }
+    }
+
+    fn candidates<'q>(borrow: &'q Self::Borrow<'_>) -> Option<&'q [u32]> {
+        match borrow {
+            Some(list) => Some(list.entity_indices()),
+            None => Some(&[])
+        }
+    }
+
+    unsafe fn fetch<'q>(borrow: &'q Self::Borrow<'_>, entity_index: u32) -> Self::Item<'q> {
+        borrow.as_ref().unwrap().get(entity_index).unwrap()
+    }
+}
+
+impl<T: Component> ComponentQuery for &T {
+    fn ticks(borrow: &Self::Borrow<'_>, entity_index: u32) -> ComponentTicks {
+        borrow.as_ref().unwrap().ticks(entity_index).unwrap()
+    }
+}
+
+/// A mutably borrowed component list.
+pub struct WriteBorrow<'w, T: Component> {
+    list: RefMut<'w, ComponentList<T>>,
+    comps: *mut T,
+    ticks: *mut ComponentTicks,
+    change_tick: u32
+}
+
+/// A component fetched mutably.
+/// Writing through it marks the component changed.
+pub struct Mut<'q, T> {
+    value: &'q mut T,
+    ticks: &'q mut ComponentTicks,
+    change_tick: u32
+}
+
+impl<'q, T> Deref for Mut<'q, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'q, T> DerefMut for Mut<'q, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.ticks.changed = self.change_tick;
+        self.value
+    }
+}
+
+impl<T: Component> Query for &mut T {
+    type Borrow<'w> = Option<WriteBorrow<'w, T>>;
+    type Item<'q> = Mut<'q, T>;
+
+    fn borrow(entity_mgr: &EntityManager) -> Self::Borrow<'_> {
+        entity_mgr.borrow_comp_list_mut::<T>().map(|mut list| {
+            let comps = list.comps_ptr();
+            let ticks = list.ticks_ptr();
+            WriteBorrow {
+                list,
+                comps,
+                ticks,
+                change_tick: entity_mgr.change_tick()
+            }
+        })
+    }
+
+    fn matches(borrow: &Self::Borrow<'_>, entity_index: u32) -> bool {
+        match borrow {
+            Some(borrow) => borrow.list.has(entity_index),
+            None => false
+        }
+    }
+
+    fn candidates<'q>(borrow: &'q Self::Borrow<'_>) -> Option<&'q [u32]> {
+        match borrow {
+            Some(borrow) => Some(borrow.list.entity_indices()),
+            None => Some(&[])
+        }
+    }
+
+    unsafe fn fetch<'q>(borrow: &'q Self::Borrow<'_>, entity_index: u32) -> Self::Item<'q> {
+        // Each entity owns a distinct slot, and is only fetched once
+        let borrow = borrow.as_ref().unwrap();
+        let ind = borrow.list.dense_index(entity_index).unwrap();
+        Mut {
+            value: &mut *borrow.comps.add(ind),
+            ticks: &mut *borrow.ticks.add(ind),
+            change_tick: borrow.change_tick
+        }
+    }
+}
+
+impl<T: Component> ComponentQuery for &mut T {
+    fn ticks(borrow: &Self::Borrow<'_>, entity_index: u32) -> ComponentTicks {
+        borrow.as_ref().unwrap().list.ticks(entity_index).unwrap()
+    }
+}
+
+/// Matches components added since the current system last ran.
+/// e.g. `entity_mgr.query::<Added<&mut SpriteComponent>>()`
+pub struct Added<Q>(PhantomData<Q>);
+
+impl<Q: ComponentQuery> Query for Added<Q> {
+    type Borrow<'w> = (Q::Borrow<'w>, u32);
+    type Item<'q> = Q::Item<'q>;
+
+    fn borrow(entity_mgr: &EntityManager) -> Self::Borrow<'_> {
+        (Q::borrow(entity_mgr), entity_mgr.last_change_tick())
+    }
+
+    fn matches((borrow, last_tick): &Self::Borrow<'_>, entity_index: u32) -> bool {
+        Q::matches(borrow, entity_index) && Q::ticks(borrow, entity_index).is_added(*last_tick)
+    }
+
+    fn candidates<'q>((borrow, _): &'q Self::Borrow<'_>) -> Option<&'q [u32]> {
+        Q::candidates(borrow)
+    }
+
+    unsafe fn fetch<'q>((borrow, _): &'q Self::Borrow<'_>, entity_index: u32) -> Self::Item<'q> {
+        Q::fetch(borrow, entity_index)
+    }
+}
+
+/// Matches components added or changed since the current system last ran.
+/// e.g. `entity_mgr.query::<(&SpriteComponent, Changed<&PositionComponent>)>()`
+pub struct Changed<Q>(PhantomData<Q>);
+
+impl<Q: ComponentQuery> Query for Changed<Q> {
+    type Borrow<'w> = (Q::Borrow<'w>, u32);
+    type Item<'q> = Q::Item<'q>;
+
+    fn borrow(entity_mgr: &EntityManager) -> Self::Borrow<'_> {
+        (Q::borrow(entity_mgr), entity_mgr.last_change_tick())
+    }
+
+    fn matches((borrow, last_tick): &Self::Borrow<'_>, entity_index: u32) -> bool {
+        Q::matches(borrow, entity_index) && Q::ticks(borrow, entity_index).is_changed(*last_tick)
+    }
+
+    fn candidates<'q>((borrow, _): &'q Self::Borrow<'_>) -> Option<&'q [u32]> {
+        Q::candidates(borrow)
+    }
+
+    unsafe fn fetch<'q>((borrow, _): &'q Self::Borrow<'_>, entity_index: u32) -> Self::Item<'q> {
+        Q::fetch(borrow, entity_index)
+    }
+}
+
+/// Implements Query for a tuple of queries.
+macro_rules! impl_query_tuple {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($name: Query),*> Query for ($($name,)*) {
+            type Borrow<'w> = ($($name::Borrow<'w>,)*);
+            type Item<'q> = ($($name::Item<'q>,)*);
+
+            fn borrow(entity_mgr: &EntityManager) -> Self::Borrow<'_> {
+                ($($name::borrow(entity_mgr),)*)
+            }
+
+            fn matches(borrow: &Self::Borrow<'_>, entity_index: u32) -> bool {
+                let ($($name,)*) = borrow;
+                $($name::matches($name, entity_index))&&*
+            }
+
+            fn candidates<'q>(borrow: &'q Self::Borrow<'_>) -> Option<&'q [u32]> {
+                // Narrow down using the smallest component list
+                let ($($name,)*) = borrow;
+                let mut candidates: Option<&'q [u32]> = None;
+                $(
+                    if let Some(inds) = $name::candidates($name) {
+                        if candidates.map_or(true, |candidates| inds.len() < candidates.len()) {
+                            candidates = Some(inds);
+                        }
+                    }
+                )*
+                candidates
+            }
+
+            unsafe fn fetch<'q>(borrow: &'q Self::Borrow<'_>, entity_index: u32) -> Self::Item<'q> {
+                let ($($name,)*) = borrow;
+                ($($name::fetch($name, entity_index),)*)
+            }
+        }
+    };
+}
+
+impl_query_tuple!(A);
+impl_query_tuple!(A, B);
+impl_query_tuple!(A, B, C);
+impl_query_tuple!(A, B, C, D);
+impl_query_tuple!(A, B, C, D, E);
+
+/// Holds the borrows for a query.
+pub struct QueryBorrow<'w, Q: Query> {
+    entities: &'w [Entity],
+    borrow: Q::Borrow<'w>
+}
+
+impl<'w, Q: Query> QueryBorrow<'w, Q> {
+    pub fn new(entity_mgr: &'w EntityManager) -> Self {
+        Self {
+            entities: entity_mgr.entities.as_slice(),
+            borrow: Q::borrow(entity_mgr)
+        }
+    }
+
+    /// Iterates over all entities matching the query.
+    /// Entities come in the order of the smallest component list queried.
+    pub fn iter(&mut self) -> QueryIter<'_, 'w, Q> {
+        QueryIter {
+            entities: self.entities,
+            candidates: Q::candidates(&self.borrow),
+            borrow: &self.borrow,
+            pos: 0
+        }
+    }
+}
+
+/// Iterator over the items of a query.
+pub struct QueryIter<'q, 'w, Q: Query> {
+    entities: &'w [Entity],
+    candidates: Option<&'q [u32]>,
+    borrow: &'q Q::Borrow<'w>,
+    pos: usize
+}
+
+impl<'q, 'w, Q: Query> Iterator for QueryIter<'q, 'w, Q> {
+    type Item = Q::Item<'q>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Without a component list to go by, every entity is checked
+        let len = match self.candidates {
+            Some(candidates) => candidates.len(),
+            None => self.entities.len()
+        };
+        while self.pos < len {
+            let entity_index = match self.candidates {
+                Some(candidates) => candidates[self.pos],
+                None => self.pos as u32
+            };
+            self.pos += 1;
+            if self.entities[entity_index as usize].exists && Q::matches(self.borrow, entity_index) {
+                // Safe, since candidates are unique and only visited once
+                return Some(unsafe { Q::fetch(self.borrow, entity_index) });
+            }
+        }
+        None
+    }
+}
+
+/// Test if queries only yield entities with every component.
+#[test]
+fn test_query_matching() {
+    use crate::components::{LogComponent, PositionComponent};
+
+    let mut entity_mgr = EntityManager::new();
+    let both_id = entity_mgr.create_entity();
+    entity_mgr.add::<PositionComponent>(both_id);
+    entity_mgr.add::<LogComponent>(both_id);
+    let pos_id = entity_mgr.create_entity();
+    entity_mgr.add::<PositionComponent>(pos_id);
+
+    let mut query = entity_mgr.query::<(EntityId, &mut PositionComponent, &LogComponent)>();
+    let ids: Vec<EntityId> = query.iter().map(|(entity_id, mut pos_comp, _)| {
+        pos_comp.x += 1;
+        entity_id
+    }).collect();
+    drop(query);
+    assert_eq!(ids, vec![both_id]);
+    assert_eq!(entity_mgr.get::<PositionComponent>(both_id).x, 1);
+    assert_eq!(entity_mgr.get::<PositionComponent>(pos_id).x, 0);
+}
+
+/// Test if conflicting borrows of a component list panic.
+#[test]
+#[should_panic]
+fn test_query_conflicting_borrows() {
+    use crate::components::PositionComponent;
+
+    let mut entity_mgr = EntityManager::new();
+    let entity_id = entity_mgr.create_entity();
+    entity_mgr.add::<PositionComponent>(entity_id);
+    entity_mgr.query::<(&mut PositionComponent, &PositionComponent)>();
+}
+
+/// Test if systems only see components added, changed or removed since they last ran.
+#[test]
+fn test_change_detection() {
+    use crate::components::PositionComponent;
+
+    fn changed_ids(entity_mgr: &EntityManager) -> Vec<EntityId> {
+        entity_mgr.query::<(EntityId, Changed<&PositionComponent>)>().iter().map(|(entity_id, _)| entity_id).collect()
+    }
+
+    let mut entity_mgr = EntityManager::new();
+    let moved_id = entity_mgr.create_entity();
+    entity_mgr.add::<PositionComponent>(moved_id);
+    let still_id = entity_mgr.create_entity();
+    entity_mgr.add::<PositionComponent>(still_id);
+
+    // Everything is new the first time a system runs
+    let mut last_run = entity_mgr.run_system(0, |entity_mgr| {
+        assert_eq!(entity_mgr.query::<Added<&PositionComponent>>().iter().count(), 2);
+        assert_eq!(changed_ids(entity_mgr), vec![moved_id, still_id]);
+    });
+    last_run = entity_mgr.run_system(last_run, |entity_mgr| {
+        assert_eq!(changed_ids(entity_mgr), vec![]);
+    });
+
+    // Only writes mark components changed, not mutable fetches
+    entity_mgr.run_system(0, |entity_mgr| {
+        for (entity_id, mut pos_comp) in entity_mgr.query::<(EntityId, &mut PositionComponent)>().iter() {
+            if entity_id == moved_id {
+                pos_comp.x += 1;
+            }
+        }
+    });
+    entity_mgr.remove::<PositionComponent>(still_id);
+    entity_mgr.run_system(last_run, |entity_mgr| {
+        assert_eq!(entity_mgr.query::<Added<&PositionComponent>>().iter().count(), 0);
+        assert_eq!(changed_ids(entity_mgr), vec![moved_id]);
+        assert_eq!(entity_mgr.removed::<PositionComponent>(), vec![still_id]);
+    });
+}
@@ -1,6 +1,7 @@
 ///
 /// Renderer that uses WGPU to render quads.
 /// Quads are rendered with pixel level precision.
+/// Tilemaps are split into chunks, and only chunks the camera can see are drawn.
 ///
 
 use winit::window;
@@ -10,6 +11,7 @@ use wgpu;
 use image::GenericImageView;
 use yaml_rust::{YamlLoader, YamlEmitter};
 use crate::texture::Texture;
+use crate::level::{self, EMPTY_TILE};
 
 pub struct Renderer {
     swap_chain: wgpu::SwapChain,
@@ -20,14 +22,24 @@ pub struct Renderer {
     tex_bind_group_layout: wgpu::BindGroupLayout,
     per_quad_bind_group_layout: wgpu::BindGroupLayout,
     glob_bind_group: wgpu::BindGroup,
+    glob_uniforms: GlobalUniforms,
+    glob_uniform_buff: wgpu::Buffer,
+    camera_pos: (i32, i32),
     textures: Vec<Texture>,
-    render_quads: Vec<RenderQuad>
+    render_quads: Vec<RenderQuad>,
+    /// Quad IDs, back to front.
+    draw_list: Vec<u32>,
+    /// IDs of removed quads, reused by new ones.
+    free_quads: Vec<u32>
 }
 
 pub const WIN_SCALE: u32 = 2;
 pub const WIN_WIDTH: u32 = 256 * WIN_SCALE;
 pub const WIN_HEIGHT: u32 = 240 * WIN_SCALE;
 
+/// Width and height of a tilemap chunk, in tiles.
+pub const CHUNK_SIZE: u32 = 16;
+
 impl Renderer {
     /// Creates a new renderer instance.
     pub async fn new(win: &window::Window) -> Self {
@@ -126,7 +138,7 @@ impl Renderer {
            &wgpu::util::BufferInitDescriptor {
                label: Some("Global Uniform Buffer"),
                contents: bytemuck::cast_slice(&[glob_uniforms]),
-               usage: wgpu::BufferUsage::UNIFORM,
+               usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
            } 
         );
         let glob_bind_group = device.create_bind_group(
@@ -221,17 +233,54 @@ impl Renderer {
             tex_bind_group_layout,
             per_quad_bind_group_layout,
             glob_bind_group,
+            glob_uniforms,
+            glob_uniform_buff,
+            camera_pos: (0, 0),
             textures,
-            render_quads: Vec::new()
+            render_quads: Vec::new(),
+            draw_list: Vec::new(),
+            free_quads: Vec::new()
         }
     }
 
     /// Creates a render quad and returns its ID.
     pub fn create_render_quad(&mut self) -> u32 {
         let r_quad = RenderQuad::new(&self.device, &self.per_quad_bind_group_layout);
-        self.render_quads.push(r_quad);
+        let quad_id = match self.free_quads.pop() {
+            Some(quad_id) => {
+                self.render_quads[quad_id as usize] = r_quad;
+                quad_id
+            },
+            None => {
+                self.render_quads.push(r_quad);
+                self.render_quads.len() as u32 - 1
+            }
+        };
+        self.draw_list.push(quad_id);
+        self.sort_draw_list();
 
-        return self.render_quads.len() as u32 - 1;
+        return quad_id;
+    }
+
+    /// Removes a render quad, so it's no longer drawn.
+    /// Its ID may be given to a quad created later.
+    pub fn remove_render_quad(&mut self, quad_id: u32) {
+        if let Some(ind) = self.draw_list.iter().position(|&id| id == quad_id) {
+            self.draw_list.remove(ind);
+
+            // Drop its buffers now, rather than when the slot is reused
+            self.render_quads[quad_id as usize] = RenderQuad::new(&self.device, &self.per_quad_bind_group_layout);
+            self.free_quads.push(quad_id);
+        }
+    }
+
+    /// Sets the order a render quad is drawn in.
+    /// Higher orders are drawn in front, and equal orders are drawn in the order they were created.
+    pub fn set_quad_draw_order(&mut self, quad_id: u32, draw_order: i32) {
+        if self.render_quads[quad_id as usize].draw_order != draw_order {
+            self.render_quads[quad_id as usize].draw_order = draw_order;
+            self.sort_draw_list();
+        }
     }
 
     /// Loads a texture and returns its ID.
@@ -260,6 +309,7 @@ impl Renderer {
         }
 
         self.render_quads[quad_id as usize].tex_id = tex_id;
+        self.render_quads[quad_id as usize].tilemap = None;
     }
 
     /// Attaches a texture as a sprite to a render quad.
@@ -281,24 +331,64 @@ impl Renderer {
         self.render_quads[quad_id as usize].vertex_buffer = RenderQuad::gen_vertex_buffer(&self.device, sprite_width, sprite_height, sprite_l, sprite_r, sprite_t, sprite_b);
 
         self.render_quads[quad_id as usize].tex_id = tex_id;
+        self.render_quads[quad_id as usize].tilemap = None;
     }
 
     /// Attaches a texture as a sprite to a render quad as tilemap source.
-    /// This always regenerates the vertex buffer, for now.
-    pub fn attach_tilemap_to_quad(&mut self, quad_id: u32, tex_id: u32, tilemap: &[u32], tilemap_width: u32, tilemap_height: u32) {
-        // Generate vertex buffer with correct texture coordinates
+    /// Tiles are drawn at `tile_size`, in pixels, whatever the size of the texture's sprites.
+    /// If the quad already has a tilemap of the same size and texture, only chunks with changed tiles are regenerated,
+    /// so single tile edits made with `SpriteComponent::set_tile` only rebuild their chunk.
+    /// Returns false, leaving the quad as it was, if the tilemap doesn't hold width * height tiles.
+    pub fn attach_tilemap_to_quad(&mut self, quad_id: u32, tex_id: u32, tilemap: &[u32], tilemap_width: u32, tilemap_height: u32, tile_size: (u32, u32)) -> bool {
+        if !level::tilemap_fits(tilemap, tilemap_width, tilemap_height) {
+            return false;
+        }
+
+        let render_quad = &mut self.render_quads[quad_id as usize];
         let new_tex = &self.textures[tex_id as usize];
-        self.render_quads[quad_id as usize].vertex_buffer = RenderQuad::gen_tilemap_vertex_buffer(&self.device, tilemap, tilemap_width, new_tex);
-        self.render_quads[quad_id as usize].vertex_count = tilemap_width * tilemap_height * QUAD_V_SIZE;
-        self.render_quads[quad_id as usize].tex_id = tex_id;
+        match &mut render_quad.tilemap {
+            Some(old) if render_quad.tex_id == tex_id && old.width == tilemap_width && old.height == tilemap_height && (old.tile_width, old.tile_height) == tile_size => {
+                for chunk_index in changed_chunks(&old.tiles, tilemap, tilemap_width, tilemap_height) {
+                    let chunk = &mut old.chunks[chunk_index];
+                    chunk.vertex_buffer = RenderQuad::gen_chunk_vertex_buffer(&self.device, tilemap, tilemap_width, chunk, new_tex, tile_size);
+                }
+                old.tiles.copy_from_slice(tilemap);
+            },
+            _ => {
+                let mut chunks = tilemap_chunks(tilemap_width, tilemap_height);
+                for chunk in chunks.iter_mut() {
+                    chunk.vertex_buffer = RenderQuad::gen_chunk_vertex_buffer(&self.device, tilemap, tilemap_width, chunk, new_tex, tile_size);
+                }
+                render_quad.tilemap = Some(Tilemap {
+                    tiles: tilemap.to_vec(),
+                    width: tilemap_width,
+                    height: tilemap_height,
+                    tile_width: tile_size.0,
+                    tile_height: tile_size.1,
+                    chunks
+                });
+            }
+        }
+        render_quad.tex_id = tex_id;
+        true
     }
 
     /// Sets the position of a render quad.
     pub fn set_quad_pos(&mut self, quad_id: u32, x: i32, y: i32) {
+        self.render_quads[quad_id as usize].pos = (x, y);
         let matrix = cgmath::Matrix4::from_translation(cgmath::Vector3::new((x * WIN_SCALE as i32) as f32, (y * WIN_SCALE as i32) as f32, 0.0));
         self.render_quads[quad_id as usize].per_quad_bind_group = RenderQuad::gen_per_quad_bind_group(&self.device, &self.per_quad_bind_group_layout, matrix);
     }
 
+    /// Moves the camera, so (x, y) is drawn at the top left of the window.
+    pub fn set_camera_pos(&mut self, x: i32, y: i32) {
+        if self.camera_pos != (x, y) {
+            self.camera_pos = (x, y);
+            self.glob_uniforms.view_mat = cgmath::Matrix4::from_translation(cgmath::Vector3::new((-x * WIN_SCALE as i32) as f32, (-y * WIN_SCALE as i32) as f32, 0.0));
+            self.queue.write_buffer(&self.glob_uniform_buff, 0, bytemuck::cast_slice(&[self.glob_uniforms]));
+        }
+    }
+
     /// Renders all render quads.
     pub fn render(&mut self) {
         // Get next frame from swap chain
@@ -335,12 +425,27 @@ impl Renderer {
 
         render_pass.set_pipeline(&self.render_pipeline);
 
-        for render_quad in self.render_quads.as_slice() {
+        // The part of the world the camera sees, in pixels
+        let view = (self.camera_pos.0, self.camera_pos.1, (WIN_WIDTH / WIN_SCALE) as i32, (WIN_HEIGHT / WIN_SCALE) as i32);
+        for &quad_id in self.draw_list.as_slice() {
+            let render_quad = &self.render_quads[quad_id as usize];
             render_pass.set_bind_group(0, &self.textures[render_quad.tex_id as usize].bind_group, &[]);
             render_pass.set_bind_group(1, &self.glob_bind_group, &[]);
             render_pass.set_bind_group(2, &render_quad.per_quad_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, render_quad.vertex_buffer.slice(..));
-            render_pass.draw(0..render_quad.vertex_count, 0..1);
+            match &render_quad.tilemap {
+                None => {
+                    render_pass.set_vertex_buffer(0, render_quad.vertex_buffer.slice(..));
+                    render_pass.draw(0..render_quad.vertex_count, 0..1);
+                },
+                Some(tilemap) => {
+                    for chunk in tilemap.chunks.iter().filter(|chunk| chunk_visible(chunk, tilemap, render_quad.pos, view)) {
+                        if let Some((vertex_buffer, vertex_count)) = &chunk.vertex_buffer {
+                            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                            render_pass.draw(0..*vertex_count, 0..1);
+                        }
+                    }
+                }
+            }
         }
 
         // Submit rendering commands to the queue
@@ -348,6 +453,13 @@ impl Renderer {
         self.queue.submit(std::iter::once(encoder.finish()));
     }
 
+    /// Sorts the draw list by draw order.
+    /// The sort is stable, so quads with equal orders stay in creation order.
+    fn sort_draw_list(&mut self) {
+        let render_quads = &self.render_quads;
+        self.draw_list.sort_by_key(|&quad_id| render_quads[quad_id as usize].draw_order);
+    }
+
     /// Loads a shader.
     fn load_shader(device: &wgpu::Device, bytes: &[u8]) -> wgpu::ShaderModule {
         device.create_shader_module(wgpu::util::make_spirv(bytes))
@@ -355,13 +467,90 @@ impl Renderer {
 }
 
 /// A quad to be rendered.
+/// Tilemap quads draw their chunks instead of their vertex buffer.
 pub struct RenderQuad {
     vertex_buffer: wgpu::Buffer,
     vertex_count: u32,
     tex_id: u32,
+    draw_order: i32,
+    pos: (i32, i32),
+    tilemap: Option<Tilemap>,
     per_quad_bind_group: wgpu::BindGroup
 }
 
+/// The tiles of a tilemap quad, and their chunks.
+struct Tilemap {
+    tiles: Vec<u32>,
+    width: u32,
+    height: u32,
+    /// Size of a tile in pixels.
+    tile_width: u32,
+    tile_height: u32,
+    chunks: Vec<TilemapChunk>
+}
+
+/// A rectangle of tiles with its own vertex buffer.
+/// Position and size are in tiles.
+struct TilemapChunk {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    /// The buffer and its vertex count, if the chunk has any tiles.
+    vertex_buffer: Option<(wgpu::Buffer, u32)>
+}
+
+/// Splits a tilemap into chunks, row by row.
+/// Chunks on the right and bottom edges may be smaller.
+fn tilemap_chunks(tilemap_width: u32, tilemap_height: u32) -> Vec<TilemapChunk> {
+    let mut chunks = Vec::new();
+    for y in (0..tilemap_height).step_by(CHUNK_SIZE as usize) {
+        for x in (0..tilemap_width).step_by(CHUNK_SIZE as usize) {
+            chunks.push(TilemapChunk {
+                x,
+                y,
+                width: CHUNK_SIZE.min(tilemap_width - x),
+                height: CHUNK_SIZE.min(tilemap_height - y),
+                vertex_buffer: None
+            });
+        }
+    }
+    chunks
+}
+
+/// Returns the index of the chunk holding a tile.
+fn chunk_index(x: u32, y: u32, tilemap_width: u32) -> usize {
+    let chunks_per_row = tilemap_width.div_ceil(CHUNK_SIZE);
+    ((y / CHUNK_SIZE) * chunks_per_row + x / CHUNK_SIZE) as usize
+}
+
+/// Returns the indices of chunks with tiles that differ.
+fn changed_chunks(old: &[u32], new: &[u32], tilemap_width: u32, tilemap_height: u32) -> Vec<usize> {
+    let mut changed = Vec::new();
+    for y in 0..tilemap_height {
+        for x in 0..tilemap_width {
+            let tile_index = (y * tilemap_width + x) as usize;
+            if old[tile_index] != new[tile_index] {
+                let chunk_index = chunk_index(x, y, tilemap_width);
+                if !changed.contains(&chunk_index) {
+                    changed.push(chunk_index);
+                }
+            }
+        }
+    }
+    changed
+}
+
+/// Returns true if a chunk overlaps the view, given as (x, y, width, height) in pixels.
+fn chunk_visible(chunk: &TilemapChunk, tilemap: &Tilemap, quad_pos: (i32, i32), view: (i32, i32, i32, i32)) -> bool {
+    let left = quad_pos.0 + (chunk.x * tilemap.tile_width) as i32;
+    let top = quad_pos.1 + (chunk.y * tilemap.tile_height) as i32;
+    let right = left + (chunk.width * tilemap.tile_width) as i32;
+    let bottom = top + (chunk.height * tilemap.tile_height) as i32;
+    let (view_x, view_y, view_width, view_height) = view;
+    left < view_x + view_width && right > view_x && top < view_y + view_height && bottom > view_y
+}
+
 const QUAD_V_SIZE: u32 = 6;
 
 impl RenderQuad {
@@ -371,6 +560,9 @@ impl RenderQuad {
             vertex_buffer: RenderQuad::gen_vertex_buffer(device, 0, 0, 0.0, 1.0, 1.0, 0.0),
             tex_id: 0,
             vertex_count: QUAD_V_SIZE,
+            draw_order: 0,
+            pos: (0, 0),
+            tilemap: None,
             per_quad_bind_group: RenderQuad::gen_per_quad_bind_group(device, per_quad_bind_group_layout, cgmath::Matrix4::from_scale(1.0)),
         }
     }
@@ -401,51 +593,55 @@ impl RenderQuad {
         return vertex_buffer;
     }
 
-    /// Generates a vertex buffer for a tilemap.
-    fn gen_tilemap_vertex_buffer(device: &wgpu::Device, tilemap: &[u32], tilemap_width: u32, texture: &Texture) -> wgpu::Buffer {
+    /// Generates a vertex buffer for one chunk of a tilemap.
+    /// Empty tiles have no vertices, so a chunk with no tiles has no buffer.
+    fn gen_chunk_vertex_buffer(device: &wgpu::Device, tilemap: &[u32], tilemap_width: u32, chunk: &TilemapChunk, texture: &Texture, tile_size: (u32, u32)) -> Option<(wgpu::Buffer, u32)> {
         // Create new coordinates
+        // Tiles take their place on the level's grid, even if the texture's sprites are another size
         let mut quad_coords = Vec::new();
-        let quad_width = (texture.metadata.sprite_width * WIN_SCALE) as f32;
-        let quad_height = (texture.metadata.sprite_height * WIN_SCALE) as f32;
-        let mut x = 0;
-        let mut y = 0;
-        for tile in tilemap {
-            let sprites_per_row = texture.width / texture.metadata.sprite_width;
-            let sprite_y = tile / sprites_per_row;
-            let sprite_x = tile - sprite_y * sprites_per_row;
-            let tex_coord_width = texture.metadata.sprite_width as f32 / texture.width as f32;
-            let tex_coord_height = texture.metadata.sprite_height as f32 / texture.height as f32;
-            let sprite_l= sprite_x as f32 * tex_coord_width;
-            let sprite_r = (sprite_x + 1) as f32 * tex_coord_width;
-            let sprite_t = (sprite_y + 1) as f32 * tex_coord_height;
-            let sprite_b = sprite_y as f32 * tex_coord_height;
-            let x_coord = (x as f32) * quad_width;
-            let y_coord = (y as f32) * quad_height;
-            quad_coords.append(&mut vec![
-                Vertex { position: [x_coord + 0.0, y_coord + quad_height, 0.0], tex_coords: [sprite_l, sprite_t] },
-                Vertex { position: [x_coord + 0.0, y_coord + 0.0, 0.0], tex_coords: [sprite_l, sprite_b] },
-                Vertex { position: [x_coord + quad_width, y_coord + 0.0, 0.0], tex_coords: [sprite_r, sprite_b] },
-                Vertex { position: [x_coord + quad_width, y_coord + 0.0, 0.0], tex_coords: [sprite_r, sprite_b] },
-                Vertex { position: [x_coord + quad_width, y_coord + quad_height, 0.0], tex_coords: [sprite_r, sprite_t] },
-                Vertex { position: [x_coord + 0.0, y_coord + quad_height, 0.0], tex_coords: [sprite_l, sprite_t] },
-            ]);
-            x += 1;
-            if x == tilemap_width {
-                x = 0;
-                y += 1;
+        let quad_width = (tile_size.0 * WIN_SCALE) as f32;
+        let quad_height = (tile_size.1 * WIN_SCALE) as f32;
+        let sprites_per_row = texture.width / texture.metadata.sprite_width;
+        let tex_coord_width = texture.metadata.sprite_width as f32 / texture.width as f32;
+        let tex_coord_height = texture.metadata.sprite_height as f32 / texture.height as f32;
+        for y in chunk.y..chunk.y + chunk.height {
+            for x in chunk.x..chunk.x + chunk.width {
+                let tile = tilemap[(y * tilemap_width + x) as usize];
+                if tile == EMPTY_TILE {
+                    continue;
+                }
+                let sprite_y = tile / sprites_per_row;
+                let sprite_x = tile - sprite_y * sprites_per_row;
+                let sprite_l= sprite_x as f32 * tex_coord_width;
+                let sprite_r = (sprite_x + 1) as f32 * tex_coord_width;
+                let sprite_t = (sprite_y + 1) as f32 * tex_coord_height;
+                let sprite_b = sprite_y as f32 * tex_coord_height;
+                let x_coord = (x as f32) * quad_width;
+                let y_coord = (y as f32) * quad_height;
+                quad_coords.append(&mut vec![
+                    Vertex { position: [x_coord + 0.0, y_coord + quad_height, 0.0], tex_coords: [sprite_l, sprite_t] },
+                    Vertex { position: [x_coord + 0.0, y_coord + 0.0, 0.0], tex_coords: [sprite_l, sprite_b] },
+                    Vertex { position: [x_coord + quad_width, y_coord + 0.0, 0.0], tex_coords: [sprite_r, sprite_b] },
+                    Vertex { position: [x_coord + quad_width, y_coord + 0.0, 0.0], tex_coords: [sprite_r, sprite_b] },
+                    Vertex { position: [x_coord + quad_width, y_coord + quad_height, 0.0], tex_coords: [sprite_r, sprite_t] },
+                    Vertex { position: [x_coord + 0.0, y_coord + quad_height, 0.0], tex_coords: [sprite_l, sprite_t] },
+                ]);
             }
-        };
+        }
+        if quad_coords.is_empty() {
+            return None;
+        }
 
         // Generate vertex buffer
         let vertex_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
+                label: Some("Tilemap Chunk Vertex Buffer"),
                 contents: bytemuck::cast_slice(quad_coords.as_slice()),
                 usage: wgpu::BufferUsage::VERTEX
             }
         );
 
-        return vertex_buffer;
+        return Some((vertex_buffer, quad_coords.len() as u32));
     }
 
     /// Generates a per quad binding group.
@@ -490,7 +686,8 @@ unsafe impl bytemuck::Zeroable for Vertex {}
 /// Holds global uniforms.
 #[derive(Copy, Clone)]
 struct GlobalUniforms {
-    proj_mat: cgmath::Matrix4<f32>
+    proj_mat: cgmath::Matrix4<f32>,
+    view_mat: cgmath::Matrix4<f32>
 }
 
 unsafe impl bytemuck::Pod for GlobalUniforms {}
@@ -510,7 +707,8 @@ impl GlobalUniforms {
             1.0
         );
         Self {
-            proj_mat
+            proj_mat,
+            view_mat: cgmath::Matrix4::from_scale(1.0)
         }
     }
 }
@@ -532,4 +730,64 @@ impl PerQuadUniforms {
             model_mat
         }
     }
-}
\ No newline at end of file
+}
+
+/// Test if tilemaps split into chunks, and edits only touch their chunk.
+#[test]
+fn test_tilemap_chunks() {
+    // 40x20 tiles is 3x2 chunks, with smaller ones on the edges
+    let chunks = tilemap_chunks(40, 20);
+    assert_eq!(chunks.len(), 6);
+    assert_eq!((chunks[2].x, chunks[2].width, chunks[5].y, chunks[5].height), (32, 8, 16, 4));
+    assert_eq!(chunk_index(33, 17, 40), 5);
+
+    let old = vec![0; 40 * 20];
+    let mut new = old.clone();
+    new[17 * 40 + 33] = 1;
+    new[17 * 40 + 34] = 1;
+    assert_eq!(changed_chunks(&old, &new, 40, 20), vec![5]);
+
+    // Only chunks overlapping the view are visible
+    let tilemap = Tilemap { tiles: old, width: 40, height: 20, tile_width: 16, tile_height: 16, chunks: Vec::new() };
+    let view = (200, 100, 256, 240);
+    let visible: Vec<_> = chunks.iter().map(|chunk| chunk_visible(chunk, &tilemap, (0, 0), view)).collect();
+    assert_eq!(visible, vec![true, true, false, true, true, false]);
+}
+
+/// Test if editing one tile of a tilemap sprite only dirties its chunk.
+#[test]
+fn test_tilemap_tile_edit() {
+    use crate::components::{Component, SpriteComponent};
+
+    let mut sprite_comp = SpriteComponent::uninit();
+    assert!(!sprite_comp.set_tile(0, 0, 1));
+
+    sprite_comp.tilemap = Some(vec![0; 40 * 20]);
+    sprite_comp.tilemap_width = 40;
+    sprite_comp.tilemap_height = 20;
+    let old = sprite_comp.tilemap.clone().unwrap();
+    assert!(sprite_comp.set_tile(20, 3, 7));
+    assert_eq!(sprite_comp.tilemap.as_ref().unwrap()[3 * 40 + 20], 7);
+    assert_eq!(changed_chunks(&old, sprite_comp.tilemap.as_ref().unwrap(), 40, 20), vec![chunk_index(20, 3, 40)]);
+
+    // Tiles outside the tilemap are rejected, rather than wrapping onto the next row
+    assert!(!sprite_comp.set_tile(40, 0, 7));
+    assert!(!sprite_comp.set_tile(0, 20, 7));
+    assert_eq!(changed_chunks(&old, sprite_comp.tilemap.as_ref().unwrap(), 40, 20).len(), 1);
+
+    // Tilemaps that don't match their size aren't indexed into
+    sprite_comp.tilemap_width = 41;
+    assert!(!sprite_comp.set_tile(40, 19, 7));
+}
+
+/// Test if tilemaps that don't match their size are caught before their chunks are built.
+#[test]
+fn test_tilemap_size_check() {
+    assert!(level::tilemap_fits(&[0; 6], 3, 2));
+    assert!(level::tilemap_fits(&[], 0, 5));
+    assert!(!level::tilemap_fits(&[0; 6], 4, 2));
+    assert!(!level::tilemap_fits(&[0; 5], 3, 2));
+
+    // Sizes too big for u32 don't wrap around to match
+    assert!(!level::tilemap_fits(&[0; 2], 0x8000_0001, 2));
+}
@@ -0,0 +1,493 @@
+///
+/// Runs systems in a declared order.
+/// Systems are grouped into stages, and may be ordered within a stage
+/// by naming systems they should run before or after.
+/// Shared systems that declare non-conflicting access run in parallel.
+///
+
+use std::any::TypeId;
+use std::fmt;
+
+use crate::commands::Commands;
+use crate::entity_manager::{self, EntityManager};
+
+/// Stages run in the order listed here.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    PreUpdate,
+    Update,
+    PostUpdate,
+    RenderPrep
+}
+
+pub const STAGES: [Stage; 4] = [Stage::PreUpdate, Stage::Update, Stage::PostUpdate, Stage::RenderPrep];
+
+/// How a system accesses the entity manager.
+enum SystemFn {
+    /// Can make structural changes, so always runs alone.
+    Exclusive(Box<dyn FnMut(&mut EntityManager) + Send>),
+    /// Only borrows components and resources, so can run alongside others.
+    Shared(Box<dyn FnMut(&EntityManager) + Send>)
+}
+
+/// How the schedule runs systems that could run in parallel.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Execution {
+    /// Runs non-conflicting shared systems on a thread pool.
+    Parallel,
+    /// Runs every system one after another, in order.
+    /// Useful for tests that need deterministic runs.
+    Sequential
+}
+
+/// Describes a system and where it runs.
+pub struct SystemDesc {
+    name: String,
+    stage: Stage,
+    before: Vec<String>,
+    after: Vec<String>,
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+    run: SystemFn,
+    last_run: u32,
+    /// Commands queued during the last run, until they're merged.
+    commands: Commands
+}
+
+impl SystemDesc {
+    /// Describes a system that needs the whole entity manager.
+    /// It never runs in parallel with other systems.
+    pub fn new<F: FnMut(&mut EntityManager) + Send + 'static>(name: &str, stage: Stage, run: F) -> Self {
+        Self::with_fn(name, stage, SystemFn::Exclusive(Box::new(run)))
+    }
+
+    /// Describes a system that only borrows components and resources.
+    /// It may run in parallel with systems whose access doesn't conflict,
+    /// so everything it borrows should be declared with `reads` and `writes`.
+    pub fn shared<F: FnMut(&EntityManager) + Send + 'static>(name: &str, stage: Stage, run: F) -> Self {
+        Self::with_fn(name, stage, SystemFn::Shared(Box::new(run)))
+    }
+
+    fn with_fn(name: &str, stage: Stage, run: SystemFn) -> Self {
+        Self {
+            name: String::from(name),
+            stage,
+            before: Vec::new(),
+            after: Vec::new(),
+            reads: Vec::new(),
+            writes: Vec::new(),
+            run,
+            last_run: 0,
+            commands: Commands::new()
+        }
+    }
+
+    /// Declares that the system reads a component or resource type.
+    pub fn reads<T: 'static>(mut self) -> Self {
+        self.reads.push(TypeId::of::<T>());
+        self
+    }
+
+    /// Declares that the system writes a component or resource type.
+    pub fn writes<T: 'static>(mut self) -> Self {
+        self.writes.push(TypeId::of::<T>());
+        self
+    }
+
+    /// Returns true if the systems can't safely run at the same time.
+    fn conflicts_with(&self, other: &SystemDesc) -> bool {
+        match (&self.run, &other.run) {
+            (SystemFn::Shared(_), SystemFn::Shared(_)) => {
+                self.writes.iter().any(|type_id| other.reads.contains(type_id) || other.writes.contains(type_id))
+                    || other.writes.iter().any(|type_id| self.reads.contains(type_id))
+            },
+            _ => true
+        }
+    }
+
+    /// Makes this system run before another.
+    pub fn before(mut self, name: &str) -> Self {
+        self.before.push(String::from(name));
+        self
+    }
+
+    /// Makes this system run after another.
+    pub fn after(mut self, name: &str) -> Self {
+        self.after.push(String::from(name));
+        self
+    }
+}
+
+/// Reasons a schedule can't be ordered.
+#[derive(Debug, PartialEq)]
+pub enum ScheduleError {
+    /// Two systems share a name.
+    DuplicateSystem(String),
+    /// A system is ordered relative to one that doesn't exist.
+    UnknownSystem { system: String, other: String },
+    /// A system must run before another that is in an earlier stage.
+    StageConflict { system: String, other: String },
+    /// Ordering constraints form a cycle between these systems.
+    Cycle(Vec<String>)
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScheduleError::DuplicateSystem(name) => write!(f, "System \"{}\" was added twice.", name),
+            ScheduleError::UnknownSystem { system, other } => write!(f, "System \"{}\" is ordered against unknown system \"{}\".", system, other),
+            ScheduleError::StageConflict { system, other } => write!(f, "System \"{}\" can't run before \"{}\", which is in an earlier stage.", system, other),
+            ScheduleError::Cycle(names) => write!(f, "Systems have cyclic ordering: {}.", names.join(", "))
+        }
+    }
+}
+
+pub struct Schedule {
+    systems: Vec<SystemDesc>,
+    order: Option<Vec<usize>>,
+    batches: Vec<Vec<usize>>,
+    execution: Execution,
+    thread_pool: Option<rayon::ThreadPool>,
+    last_run_tick: u32
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self {
+            systems: Vec::new(),
+            order: None,
+            batches: Vec::new(),
+            execution: Execution::Parallel,
+            thread_pool: None,
+            last_run_tick: 0
+        }
+    }
+
+    /// Adds a system to the schedule.
+    pub fn add_system(&mut self, system: SystemDesc) {
+        self.systems.push(system);
+        self.order = None;
+    }
+
+    /// Sets how systems are run. Defaults to parallel.
+    pub fn set_execution(&mut self, execution: Execution) {
+        self.execution = execution;
+    }
+
+    /// Works out the order systems run in.
+    /// Called automatically by `run`, but useful to catch errors early.
+    pub fn build(&mut self) -> Result<(), ScheduleError> {
+        if self.order.is_none() {
+            let (order, runs_after) = self.sort_systems()?;
+            self.batches = self.batch_systems(&order, &runs_after);
+            self.order = Some(order);
+        }
+        Ok(())
+    }
+
+    /// Returns the names of systems in the order they run.
+    pub fn system_order(&mut self) -> Result<Vec<&str>, ScheduleError> {
+        self.build()?;
+        let systems = &self.systems;
+        Ok(self.order.as_ref().unwrap().iter().map(|&i| systems[i].name.as_str()).collect())
+    }
+
+    /// Returns the names of systems grouped by which can run in parallel.
+    pub fn system_batches(&mut self) -> Result<Vec<Vec<&str>>, ScheduleError> {
+        self.build()?;
+        let systems = &self.systems;
+        Ok(self.batches.iter().map(|batch| batch.iter().map(|&i| systems[i].name.as_str()).collect()).collect())
+    }
+
+    /// Runs every system once, stage by stage.
+    /// Panics if the schedule can't be ordered.
+    pub fn run(&mut self, entity_mgr: &mut EntityManager) {
+        if let Err(err) = self.build() {
+            panic!("{}", err);
+        }
+
+        // Each run is one tick for events
+        entity_mgr.update_events();
+
+        // Every system has seen removals from before the last run by now
+        entity_mgr.clear_removed(self.last_run_tick);
+        self.last_run_tick = entity_mgr.change_tick();
+
+        // Structural changes queued by systems are applied between stages
+        for &stage in STAGES.iter() {
+            for batch_index in 0..self.batches.len() {
+                if self.systems[self.batches[batch_index][0]].stage == stage {
+                    self.run_batch(batch_index, entity_mgr);
+                }
+            }
+            entity_mgr.apply_commands();
+        }
+    }
+
+    /// Runs a batch of systems.
+    fn run_batch(&mut self, batch_index: usize, entity_mgr: &mut EntityManager) {
+        let batch = &self.batches[batch_index];
+        if batch.len() == 1 || self.execution == Execution::Sequential {
+            for &i in batch {
+                let system = &mut self.systems[i];
+                let run = &mut system.run;
+                system.last_run = entity_mgr.run_system(system.last_run, |entity_mgr| match run {
+                    SystemFn::Exclusive(run) => run(entity_mgr),
+                    SystemFn::Shared(run) => run(entity_mgr)
+                });
+                entity_mgr.queue_commands(entity_manager::take_system_commands());
+            }
+            return;
+        }
+
+        // Batches only hold shared systems, so they can all borrow the manager at once
+        let this_run = entity_mgr.advance_change_tick();
+        let thread_pool = self.thread_pool.get_or_insert_with(|| {
+            rayon::ThreadPoolBuilder::new().build().expect("Could not create system thread pool.")
+        });
+        let systems = &mut self.systems;
+        let shared_mgr: &EntityManager = entity_mgr;
+        thread_pool.scope(|scope| {
+            for (_, system) in systems.iter_mut().enumerate().filter(|(i, _)| batch.contains(i)) {
+                if let SystemFn::Shared(run) = &mut system.run {
+                    let last_run = system.last_run;
+                    let commands = &mut system.commands;
+                    scope.spawn(move |_| {
+                        entity_manager::with_last_change_tick(last_run, || run(shared_mgr));
+                        *commands = entity_manager::take_system_commands();
+                    });
+                }
+            }
+        });
+
+        // Commands are merged in system order, however the threads finished
+        for &i in batch {
+            systems[i].last_run = this_run;
+            entity_mgr.queue_commands(std::mem::take(&mut systems[i].commands));
+        }
+
+        // Changes made after the batch count as new to its systems
+        entity_mgr.advance_change_tick();
+    }
+
+    /// Groups sorted systems into batches that can run in parallel.
+    /// A batch only holds systems from one stage that don't conflict or depend on each other.
+    fn batch_systems(&self, order: &[usize], runs_after: &[Vec<usize>]) -> Vec<Vec<usize>> {
+        let mut batches: Vec<Vec<usize>> = Vec::new();
+        for &i in order {
+            let system = &self.systems[i];
+            let joins_last = match batches.last() {
+                Some(batch) => batch.iter().all(|&j| {
+                    self.systems[j].stage == system.stage
+                        && !system.conflicts_with(&self.systems[j])
+                        && !runs_after[i].contains(&j)
+                }),
+                None => false
+            };
+            if joins_last {
+                batches.last_mut().unwrap().push(i);
+            }
+            else {
+                batches.push(vec![i]);
+            }
+        }
+        batches
+    }
+
+    /// Sorts systems by stage, then by ordering constraints.
+    /// Ties keep the order systems were added in.
+    /// Also returns which systems each system must run after.
+    fn sort_systems(&self) -> Result<(Vec<usize>, Vec<Vec<usize>>), ScheduleError> {
+        // Turn constraints into edges between system indices
+        let mut runs_after: Vec<Vec<usize>> = vec![Vec::new(); self.systems.len()];
+        for (i, system) in self.systems.iter().enumerate() {
+            if self.find_system(&system.name) != Some(i) {
+                return Err(ScheduleError::DuplicateSystem(system.name.clone()));
+            }
+
+            for other in &system.after {
+                let j = self.find_dependency(system, other)?;
+                self.check_stages(j, i)?;
+                runs_after[i].push(j);
+            }
+            for other in &system.before {
+                let j = self.find_dependency(system, other)?;
+                self.check_stages(i, j)?;
+                runs_after[j].push(i);
+            }
+        }
+
+        // Constraints across stages are already met by stage order
+        for (i, deps) in runs_after.iter_mut().enumerate() {
+            let stage = self.systems[i].stage;
+            deps.retain(|&j| self.systems[j].stage == stage);
+        }
+
+        let mut order = Vec::with_capacity(self.systems.len());
+        for &stage in STAGES.iter() {
+            let mut pending: Vec<usize> = (0..self.systems.len())
+                .filter(|&i| self.systems[i].stage == stage)
+                .collect();
+
+            // Repeatedly take the first system whose dependencies have all run
+            while !pending.is_empty() {
+                let ready = pending.iter().position(|&i| {
+                    runs_after[i].iter().all(|j| order.contains(j))
+                });
+                match ready {
+                    Some(pos) => order.push(pending.remove(pos)),
+                    None => {
+                        let names = pending.iter().map(|&i| self.systems[i].name.clone()).collect();
+                        return Err(ScheduleError::Cycle(names));
+                    }
+                }
+            }
+        }
+
+        Ok((order, runs_after))
+    }
+
+    /// Returns the index of the system with this name.
+    fn find_system(&self, name: &str) -> Option<usize> {
+        self.systems.iter().position(|system| system.name == name)
+    }
+
+    /// Returns the index of a system named in another system's constraints.
+    fn find_dependency(&self, system: &SystemDesc, other: &str) -> Result<usize, ScheduleError> {
+        self.find_system(other).ok_or_else(|| ScheduleError::UnknownSystem {
+            system: system.name.clone(),
+            other: String::from(other)
+        })
+    }
+
+    /// Errors if system `first` can't run before `second` because of their stages.
+    fn check_stages(&self, first: usize, second: usize) -> Result<(), ScheduleError> {
+        if self.systems[first].stage > self.systems[second].stage {
+            return Err(ScheduleError::StageConflict {
+                system: self.systems[first].name.clone(),
+                other: self.systems[second].name.clone()
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Test if systems run by stage, then by their constraints.
+#[test]
+fn test_schedule_order() {
+    let mut schedule = Schedule::new();
+    schedule.add_system(SystemDesc::new("render", Stage::RenderPrep, |_| {}));
+    schedule.add_system(SystemDesc::new("logging", Stage::Update, |_| {}).after("player"));
+    schedule.add_system(SystemDesc::new("player", Stage::Update, |_| {}));
+    schedule.add_system(SystemDesc::new("input", Stage::PreUpdate, |_| {}).before("player"));
+    assert_eq!(schedule.system_order().unwrap(), vec!["input", "player", "logging", "render"]);
+}
+
+/// Test if bad constraints are reported.
+#[test]
+fn test_schedule_errors() {
+    let mut schedule = Schedule::new();
+    schedule.add_system(SystemDesc::new("a", Stage::Update, |_| {}).after("b"));
+    schedule.add_system(SystemDesc::new("b", Stage::Update, |_| {}).after("a"));
+    assert_eq!(schedule.build(), Err(ScheduleError::Cycle(vec![String::from("a"), String::from("b")])));
+
+    let mut schedule = Schedule::new();
+    schedule.add_system(SystemDesc::new("a", Stage::PostUpdate, |_| {}).before("b"));
+    schedule.add_system(SystemDesc::new("b", Stage::Update, |_| {}));
+    assert_eq!(schedule.build(), Err(ScheduleError::StageConflict { system: String::from("a"), other: String::from("b") }));
+
+    let mut schedule = Schedule::new();
+    schedule.add_system(SystemDesc::new("a", Stage::Update, |_| {}).after("missing"));
+    assert_eq!(schedule.build(), Err(ScheduleError::UnknownSystem { system: String::from("a"), other: String::from("missing") }));
+}
+
+/// Test if only non-conflicting shared systems are batched together.
+#[test]
+fn test_schedule_batches() {
+    use crate::components::{LogComponent, PositionComponent};
+
+    let mut schedule = Schedule::new();
+    schedule.add_system(SystemDesc::shared("a", Stage::Update, |_| {}).reads::<PositionComponent>());
+    schedule.add_system(SystemDesc::shared("b", Stage::Update, |_| {}).reads::<PositionComponent>().writes::<LogComponent>());
+    schedule.add_system(SystemDesc::shared("c", Stage::Update, |_| {}).writes::<PositionComponent>());
+    schedule.add_system(SystemDesc::shared("d", Stage::Update, |_| {}).reads::<LogComponent>().after("c"));
+    schedule.add_system(SystemDesc::shared("e", Stage::Update, |_| {}));
+    schedule.add_system(SystemDesc::new("f", Stage::Update, |_| {}));
+    schedule.add_system(SystemDesc::shared("g", Stage::PostUpdate, |_| {}));
+    assert_eq!(schedule.system_batches().unwrap(), vec![vec!["a", "b"], vec!["c"], vec!["d", "e"], vec!["f"], vec!["g"]]);
+}
+
+/// Test if parallel and sequential runs give the same results.
+#[test]
+fn test_schedule_parallel() {
+    use crate::components::{LogComponent, PositionComponent};
+
+    for &execution in &[Execution::Parallel, Execution::Sequential] {
+        let mut entity_mgr = EntityManager::new();
+        for _ in 0..100 {
+            let entity_id = entity_mgr.create_entity();
+            entity_mgr.add::<PositionComponent>(entity_id);
+            entity_mgr.add::<LogComponent>(entity_id);
+        }
+
+        let mut schedule = Schedule::new();
+        schedule.set_execution(execution);
+        schedule.add_system(SystemDesc::shared("move", Stage::Update, |entity_mgr| {
+            for mut pos_comp in entity_mgr.query::<&mut PositionComponent>().iter() {
+                pos_comp.x += 1;
+            }
+        }).writes::<PositionComponent>());
+        schedule.add_system(SystemDesc::shared("log", Stage::Update, |entity_mgr| {
+            for mut log_comp in entity_mgr.query::<&mut LogComponent>().iter() {
+                log_comp.message.push('.');
+            }
+        }).writes::<LogComponent>());
+        assert_eq!(schedule.system_batches().unwrap().len(), 1);
+
+        for _ in 0..3 {
+            schedule.run(&mut entity_mgr);
+        }
+        assert!(entity_mgr.query::<&PositionComponent>().iter().all(|pos_comp| pos_comp.x == 3));
+        assert!(entity_mgr.query::<&LogComponent>().iter().all(|log_comp| log_comp.message == "..."));
+    }
+}
+
+/// Test if shared systems can queue commands and events in parallel.
+#[test]
+fn test_schedule_parallel_commands() {
+    use crate::components::{Component, PositionComponent};
+    use crate::events::{EventReader, Events};
+
+    for &execution in &[Execution::Parallel, Execution::Sequential] {
+        let mut entity_mgr = EntityManager::new();
+        entity_mgr.add_event::<i32>();
+
+        let mut schedule = Schedule::new();
+        schedule.set_execution(execution);
+        for &x in &[1, 2] {
+            schedule.add_system(SystemDesc::shared(&x.to_string(), Stage::Update, move |entity_mgr| {
+                let mut pos_comp = PositionComponent::uninit();
+                pos_comp.x = x;
+                entity_mgr.commands().spawn().with(pos_comp);
+                entity_mgr.event_writer::<i32>().send(x);
+            }));
+        }
+        assert_eq!(schedule.system_batches().unwrap().len(), 1);
+
+        let mut reader = EventReader::<i32>::new();
+        for run in 1..=3 {
+            schedule.run(&mut entity_mgr);
+            let xs: Vec<i32> = entity_mgr.query::<&PositionComponent>().iter().map(|pos_comp| pos_comp.x).collect();
+            assert_eq!(xs.len(), run * 2);
+            assert_eq!(&xs[xs.len() - 2..], &[1, 2]);
+            let events: Vec<i32> = reader.iter(&entity_mgr.res::<Events<i32>>()).copied().collect();
+            assert_eq!(events, vec![1, 2]);
+        }
+    }
+}
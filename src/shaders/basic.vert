@@ -8,6 +8,7 @@ layout(location=0) out vec2 v_tex_coords;
 layout(set=1, binding=0)
 uniform Globals {
     mat4 proj_mat;
+    mat4 view_mat;
 };
 
 layout(set=2, binding=0)
@@ -17,5 +18,5 @@ uniform PerQuad {
 
 void main() {
     v_tex_coords = a_tex_coords;
-    gl_Position = proj_mat * model_mat * vec4(a_position, 1.0);
+    gl_Position = proj_mat * view_mat * model_mat * vec4(a_position, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,190 @@
+///
+/// Binary snapshots of game state.
+/// Values are written little endian, in the order they are saved.
+///
+
+use std::fmt;
+
+/// Reasons a snapshot can't be loaded.
+#[derive(Debug, PartialEq)]
+pub enum SnapshotError {
+    /// The data ended before a value could be read.
+    UnexpectedEnd,
+    /// The data doesn't start with the snapshot header.
+    BadHeader,
+    /// A component type in the snapshot wasn't registered.
+    UnknownComponent(String),
+    /// A value was out of range.
+    InvalidData(String)
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::UnexpectedEnd => write!(f, "Snapshot ended unexpectedly."),
+            SnapshotError::BadHeader => write!(f, "Data is not a snapshot."),
+            SnapshotError::UnknownComponent(name) => write!(f, "Snapshot contains unregistered component \"{}\".", name),
+            SnapshotError::InvalidData(msg) => write!(f, "Snapshot is invalid: {}", msg)
+        }
+    }
+}
+
+/// Something that can be saved to and loaded from a snapshot.
+pub trait Snapshot: Sized {
+    /// Writes the value.
+    fn save(&self, writer: &mut SnapshotWriter);
+
+    /// Reads a value written by `save`.
+    fn load(reader: &mut SnapshotReader) -> Result<Self, SnapshotError>;
+}
+
+pub struct SnapshotWriter {
+    bytes: Vec<u8>
+}
+
+impl SnapshotWriter {
+    pub fn new() -> Self {
+        Self {
+            bytes: Vec::new()
+        }
+    }
+
+    /// Writes a value.
+    pub fn write<T: Snapshot>(&mut self, value: &T) {
+        value.save(self);
+    }
+
+    /// Writes raw bytes.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Returns the written bytes.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl Default for SnapshotWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct SnapshotReader<'a> {
+    bytes: &'a [u8],
+    pos: usize
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            pos: 0
+        }
+    }
+
+    /// Reads a value.
+    pub fn read<T: Snapshot>(&mut self) -> Result<T, SnapshotError> {
+        T::load(self)
+    }
+
+    /// Reads a number of raw bytes.
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() - self.pos < len {
+            return Err(SnapshotError::UnexpectedEnd);
+        }
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    /// Returns true if every byte has been read.
+    pub fn is_done(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+}
+
+/// Implements Snapshot for a number type.
+macro_rules! impl_snapshot_num {
+    ($num_type:ty, $size:expr) => {
+        impl Snapshot for $num_type {
+            fn save(&self, writer: &mut SnapshotWriter) {
+                writer.write_bytes(&self.to_le_bytes());
+            }
+
+            fn load(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+                let mut bytes = [0; $size];
+                bytes.copy_from_slice(reader.read_bytes($size)?);
+                Ok(<$num_type>::from_le_bytes(bytes))
+            }
+        }
+    };
+}
+
+impl_snapshot_num!(u32, 4);
+impl_snapshot_num!(i32, 4);
+impl_snapshot_num!(f32, 4);
+
+impl Snapshot for bool {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write_bytes(&[*self as u8]);
+    }
+
+    fn load(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        match reader.read_bytes(1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            x => Err(SnapshotError::InvalidData(format!("{} is not a bool.", x)))
+        }
+    }
+}
+
+impl Snapshot for String {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&(self.len() as u32));
+        writer.write_bytes(self.as_bytes());
+    }
+
+    fn load(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        let len = reader.read::<u32>()? as usize;
+        let bytes = reader.read_bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| SnapshotError::InvalidData(String::from("String is not UTF-8.")))
+    }
+}
+
+impl<T: Snapshot> Snapshot for Option<T> {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&self.is_some());
+        if let Some(value) = self {
+            writer.write(value);
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        if reader.read::<bool>()? {
+            Ok(Some(reader.read()?))
+        }
+        else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T: Snapshot> Snapshot for Vec<T> {
+    fn save(&self, writer: &mut SnapshotWriter) {
+        writer.write(&(self.len() as u32));
+        for value in self {
+            writer.write(value);
+        }
+    }
+
+    fn load(reader: &mut SnapshotReader) -> Result<Self, SnapshotError> {
+        let len = reader.read::<u32>()?;
+        let mut values = Vec::new();
+        for _ in 0..len {
+            values.push(reader.read()?);
+        }
+        Ok(values)
+    }
+}
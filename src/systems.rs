@@ -1,3 +1,6 @@
 pub mod logging_system;
 pub mod draw_system;
-pub mod player_system;
\ No newline at end of file
+pub mod player_system;
+pub mod input_system;
+pub mod transform_system;
+pub mod camera_system;
\ No newline at end of file
@@ -0,0 +1,22 @@
+///
+/// Moves the camera to follow the player.
+///
+
+use crate::{camera::Camera, components::{PlayerComponent, PositionComponent}, entity_manager::EntityManager, level::LevelBounds};
+
+// Follow the player, and keep them on screen if the camera doesn't scroll back.
+pub fn update(entity_mgr: &EntityManager) {
+    let mut camera = entity_mgr.res_mut::<Camera>();
+    let bounds = entity_mgr.res::<LevelBounds>();
+    let mut query = entity_mgr.query::<(&PlayerComponent, &mut PositionComponent)>();
+    for (_, mut pos_comp) in query.iter() {
+        // Only write when clamping, so the position isn't marked changed every tick
+        let prec_x = pos_comp.prec_x;
+        camera.follow(prec_x, &bounds);
+        if prec_x < camera.x {
+            pos_comp.prec_x = camera.x;
+            pos_comp.spd_x = 0.0;
+            pos_comp.x = pos_comp.prec_x as i32;
+        }
+    }
+}
@@ -2,31 +2,79 @@
 /// Draws graphics onto the screen.
 /// 
 
-use crate::{entity_manager::EntityManager, renderer::Renderer};
-
-// Iterate over entities and update them.
-pub fn update(entity_mgr: &mut EntityManager, renderer: &mut Renderer) {
-    for entity_id in 0..entity_mgr.entities.len() {
-        if entity_mgr.entities[entity_id].exists && entity_mgr.entities[entity_id].use_draw {
-            let sprite_comp = entity_mgr.get_sprite_comp(entity_id as u32);
-
-            // If the sprite should update, create a new quad
-            if sprite_comp.should_update {
-                sprite_comp.quad_id = renderer.create_render_quad();
-                let tex_id = renderer.load_texture(sprite_comp.tex_name.as_str());
-                if sprite_comp.tilemap == None {
-                    renderer.attach_sprite_to_quad(sprite_comp.quad_id, tex_id, sprite_comp.sprite_index);
-                }
-                else {
-                    renderer.attach_tilemap_to_quad(sprite_comp.quad_id, tex_id, sprite_comp.tilemap.as_mut().unwrap().as_slice(), sprite_comp.tilemap_width, sprite_comp.tilemap_height);
-                }
-                sprite_comp.should_update = false;
+use std::collections::HashMap;
+
+use crate::{camera::Camera, components::{PositionComponent, SpriteComponent}, entity_manager::{EntityId, EntityManager}, query::Changed, renderer::Renderer};
+
+/// Remembers the quad each sprite was drawn with.
+/// Removed sprites can't be read anymore, so this is how their quads are found again.
+#[derive(Default)]
+pub struct SpriteQuads {
+    quads: HashMap<EntityId, u32>
+}
+
+impl SpriteQuads {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the quad a sprite entity is drawn with.
+    pub fn get(&self, entity_id: EntityId) -> Option<u32> {
+        self.quads.get(&entity_id).copied()
+    }
+
+    /// Forgets sprites removed since the current system last ran, and returns their quads.
+    pub fn take_removed(&mut self, entity_mgr: &EntityManager) -> Vec<u32> {
+        entity_mgr.removed::<SpriteComponent>().into_iter()
+            .filter_map(|entity_id| self.quads.remove(&entity_id))
+            .collect()
+    }
+
+    /// Gives new sprites a quad from `create_quad`.
+    /// Replaced sprites start without a quad, so they get back the one their entity already had.
+    pub fn assign<F: FnMut() -> u32>(&mut self, entity_mgr: &EntityManager, mut create_quad: F) {
+        for (entity_id, mut sprite_comp) in entity_mgr.query::<(EntityId, Changed<&mut SpriteComponent>)>().iter() {
+            let quad_id = *self.quads.entry(entity_id).or_insert_with(&mut create_quad);
+            if sprite_comp.quad_id != quad_id {
+                sprite_comp.quad_id = quad_id;
             }
+        }
+    }
+}
+
+// Update quads for sprites and positions that changed.
+pub fn update(entity_mgr: &EntityManager, sprite_quads: &mut SpriteQuads) {
+    let mut renderer = entity_mgr.res_mut::<Renderer>();
+
+    // Pixel aligned, so tiles don't shimmer while scrolling
+    let camera = entity_mgr.res::<Camera>();
+    renderer.set_camera_pos(camera.x as i32, camera.y as i32);
+
+    // Removed sprites free their quad, before new sprites might reuse it
+    for quad_id in sprite_quads.take_removed(entity_mgr) {
+        renderer.remove_render_quad(quad_id);
+    }
 
-            // Place the quad at a position
-            let quad_id = sprite_comp.quad_id;
-            let pos_comp = entity_mgr.get_pos_comp(entity_id as u32);
-            renderer.set_quad_pos(quad_id, pos_comp.x, pos_comp.y);
+    // New sprites get a quad, and replaced ones keep theirs
+    sprite_quads.assign(entity_mgr, || renderer.create_render_quad());
+
+    // Changed sprites get their texture reattached, and tilemaps rebuild only the chunks that changed
+    for (sprite_comp, pos_comp) in entity_mgr.query::<(Changed<&SpriteComponent>, &PositionComponent)>().iter() {
+        let tex_id = renderer.load_texture(sprite_comp.tex_name.as_str());
+        match &sprite_comp.tilemap {
+            None => renderer.attach_sprite_to_quad(sprite_comp.quad_id, tex_id, sprite_comp.sprite_index),
+            Some(tilemap) => {
+                if !renderer.attach_tilemap_to_quad(sprite_comp.quad_id, tex_id, tilemap.as_slice(), sprite_comp.tilemap_width, sprite_comp.tilemap_height, (sprite_comp.tile_width, sprite_comp.tile_height)) {
+                    eprintln!("Tilemap has {} tiles, but is {}x{}.", tilemap.len(), sprite_comp.tilemap_width, sprite_comp.tilemap_height);
+                }
+            }
         }
+        renderer.set_quad_draw_order(sprite_comp.quad_id, sprite_comp.draw_order);
+        renderer.set_quad_pos(sprite_comp.quad_id, pos_comp.x, pos_comp.y);
+    }
+
+    // Only moved quads are placed again
+    for (sprite_comp, pos_comp) in entity_mgr.query::<(&SpriteComponent, Changed<&PositionComponent>)>().iter() {
+        renderer.set_quad_pos(sprite_comp.quad_id, pos_comp.x, pos_comp.y);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,10 @@
+///
+/// Advances the input state once per frame.
+/// 
+
+use crate::{entity_manager::EntityManager, input_manager::InputManager};
+
+// Update the input manager resource.
+pub fn update(entity_mgr: &EntityManager) {
+    entity_mgr.res_mut::<InputManager>().update();
+}
\ No newline at end of file
@@ -2,7 +2,7 @@
 /// Handles logic for player.
 /// 
 
-use crate::{components::PositionComponent, entity_manager::EntityManager, components::LogComponent, input_manager::{self, InputManager}};
+use crate::{components::{PlayerComponent, PositionComponent}, entity_manager::EntityManager, components::LogComponent, input_manager::{self, InputManager}, level::LevelBounds};
 
 // Taken from jdaster64's SMB physics engine guide
 const MIN_WALK_VEL: f32 = 0.13;
@@ -17,18 +17,34 @@ const HOLD_GRAV: f32 = 0.2;
 const FALL_GRAV: f32 = 0.7;
 const MAX_V_VEL: f32 = 4.8;
 
+// Size of Ferris, in pixels
+const PLAYER_WIDTH: f32 = 8.0;
+const PLAYER_HEIGHT: f32 = 8.0;
+
+/// Things that happen to the player.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PlayerEvent {
+    Jumped,
+    Landed
+}
+
 // Iterate over entities and update them.
-pub fn update(entity_mgr: &mut EntityManager, input_mgr: &InputManager) {
-    for entity_id in 0..entity_mgr.entities.len() {
-        if entity_mgr.entities[entity_id].exists && entity_mgr.entities[entity_id].use_player {
-            update_entity(entity_mgr.get_pos_comp(entity_id as u32),  input_mgr);
+pub fn update(entity_mgr: &EntityManager) {
+    let input_mgr = entity_mgr.res::<InputManager>();
+    let bounds = entity_mgr.res::<LevelBounds>();
+    let mut event_writer = entity_mgr.event_writer::<PlayerEvent>();
+    let mut query = entity_mgr.query::<(&PlayerComponent, &mut PositionComponent)>();
+    for (_, mut pos_comp) in query.iter() {
+        if let Some(event) = update_entity(&mut pos_comp, &input_mgr, &bounds) {
+            event_writer.send(event);
         }
     }
 }
 
-/// Renders sprites onto screen.
-pub fn update_entity(pos_comp: &mut PositionComponent, input_mgr: &InputManager) {
+/// Moves the player, returning anything notable that happened.
+pub fn update_entity(pos_comp: &mut PositionComponent, input_mgr: &InputManager, bounds: &LevelBounds) -> Option<PlayerEvent> {
     let in_air = pos_comp.spd_y.abs() > 0.01;
+    let mut event = None;
 
     // Handle horizontal movement
     if input_mgr.is_button_down(input_manager::ButtonCode::LEFT) {
@@ -65,6 +81,7 @@ pub fn update_entity(pos_comp: &mut PositionComponent, input_mgr: &InputManager)
     // If UP is pressed, apply initial velocity
     if input_mgr.is_button_pressed(input_manager::ButtonCode::A) && !in_air {
         pos_comp.spd_y = -JUMP_ACC;
+        event = Some(PlayerEvent::Jumped);
     }
 
     // If maximum falling speed has been reached, clip it
@@ -76,23 +93,26 @@ pub fn update_entity(pos_comp: &mut PositionComponent, input_mgr: &InputManager)
     pos_comp.prec_x += pos_comp.spd_x;
     pos_comp.prec_y += pos_comp.spd_y;
 
-    // Set up temporary bounds
-    let v_offset = 4.0;
-    let h_offset = 8.0;
-    if pos_comp.prec_y > 196.0 + v_offset {
-        pos_comp.prec_y = 196.0 + v_offset;
+    // Keep inside the level, standing on its floor
+    if pos_comp.prec_y > bounds.floor - PLAYER_HEIGHT {
+        pos_comp.prec_y = bounds.floor - PLAYER_HEIGHT;
         pos_comp.spd_y = 0.0;
+        if in_air {
+            event = Some(PlayerEvent::Landed);
+        }
     }
     if pos_comp.prec_x < 0.0 {
         pos_comp.prec_x = 0.0;
         pos_comp.spd_x = 0.0;
     }
-    if pos_comp.prec_x > 256.0 - h_offset {
-        pos_comp.prec_x = 256.0 - h_offset;
+    if pos_comp.prec_x > bounds.width - PLAYER_WIDTH {
+        pos_comp.prec_x = bounds.width - PLAYER_WIDTH;
         pos_comp.spd_x = 0.0;
     }
 
     // Resolve precise x and y values to actual
     pos_comp.x = pos_comp.prec_x as i32;
     pos_comp.y = pos_comp.prec_y as i32;
+
+    event
 }
\ No newline at end of file
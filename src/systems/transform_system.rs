@@ -0,0 +1,53 @@
+///
+/// Moves child entities along with their parents.
+/// 
+
+use crate::{components::{ChildrenComponent, ParentComponent, PositionComponent}, entity_manager::{EntityId, EntityManager}};
+
+// Propagate positions down from every root entity.
+pub fn update(entity_mgr: &mut EntityManager) {
+    let mut stack: Vec<EntityId> = {
+        let mut query = entity_mgr.query::<(EntityId, &ChildrenComponent)>();
+        query.iter()
+            .filter(|(entity_id, _)| !entity_mgr.has::<ParentComponent>(*entity_id))
+            .map(|(entity_id, _)| entity_id)
+            .collect()
+    };
+
+    // Parents are always visited before their children
+    while let Some(parent_id) = stack.pop() {
+        let (parent_x, parent_y) = match entity_mgr.try_get::<PositionComponent>(parent_id) {
+            Ok(pos_comp) => (pos_comp.x, pos_comp.y),
+            Err(_) => (0, 0)
+        };
+
+        let children = match entity_mgr.try_get::<ChildrenComponent>(parent_id) {
+            Ok(children_comp) => children_comp.children.clone(),
+            Err(_) => continue
+        };
+        for child_id in children {
+            let (x, y) = match entity_mgr.try_get::<ParentComponent>(child_id) {
+                Ok(parent_comp) => (parent_x + parent_comp.offset_x, parent_y + parent_comp.offset_y),
+                Err(_) => continue
+            };
+
+            // Only write if the child moved, so it isn't marked changed for nothing
+            let moved = match entity_mgr.try_get::<PositionComponent>(child_id) {
+                Ok(pos_comp) => pos_comp.x != x || pos_comp.y != y,
+                Err(_) => false
+            };
+            if moved {
+                update_entity(entity_mgr.get_mut::<PositionComponent>(child_id), x, y);
+            }
+            stack.push(child_id);
+        }
+    }
+}
+
+/// Places a child at its world position.
+pub fn update_entity(pos_comp: &mut PositionComponent, x: i32, y: i32) {
+    pos_comp.x = x;
+    pos_comp.y = y;
+    pos_comp.prec_x = x as f32;
+    pos_comp.prec_y = y as f32;
+}
\ No newline at end of file
@@ -0,0 +1,342 @@
+///
+/// Imports maps made with the Tiled editor, saved as TMX or JSON.
+/// Tile layers become level layers, objects become spawn points, and the
+/// custom properties `solid`, `one_way` and `hazard` are read from tiles.
+/// Layers are drawn by their name, like ASCII levels, unless they have an
+/// int property `draw_order`.
+///
+/// Maps should use one tileset, embedded in the map, whose image matches the
+/// tilemap's texture. Tile layers should be stored as CSV.
+/// Tiles are drawn at the map's tile size, stretching the texture's sprites if they differ.
+///
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use json::JsonValue;
+
+use crate::level::{self, Level, SpawnPoint, TileLayer, TileProperties, EMPTY_TILE};
+
+/// Tiled stores flips and rotations in the top bits of tile ids.
+const FLIP_FLAGS: u32 = 0xF000_0000;
+
+/// Reasons a Tiled map can't be imported.
+#[derive(Debug, PartialEq)]
+pub enum TiledError {
+    /// The file couldn't be read or parsed.
+    Load(String),
+    /// The map uses a feature that isn't supported.
+    Unsupported(String),
+    /// The map's contents don't make sense.
+    BadMap(String)
+}
+
+impl fmt::Display for TiledError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TiledError::Load(msg) => write!(f, "Could not load Tiled map: {}", msg),
+            TiledError::Unsupported(msg) => write!(f, "Tiled map uses an unsupported feature: {}", msg),
+            TiledError::BadMap(msg) => write!(f, "Tiled map is invalid: {}", msg)
+        }
+    }
+}
+
+/// Size of a map and its tiles.
+struct MapSize {
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32
+}
+
+/// Loads a map, picking the format from the file extension.
+pub fn load(path: &str) -> Result<Level, TiledError> {
+    let contents = std::fs::read_to_string(path).map_err(|err| TiledError::Load(format!("{}: {}", path, err)))?;
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("tmx") => parse_tmx(&contents),
+        Some("json") => parse_json(&contents),
+        _ => Err(TiledError::Load(format!("{}: expected a .tmx or .json file.", path)))
+    }
+}
+
+/// Parses a map saved as TMX.
+pub fn parse_tmx(contents: &str) -> Result<Level, TiledError> {
+    let doc = roxmltree::Document::parse(contents).map_err(|err| TiledError::Load(err.to_string()))?;
+    let map = doc.root_element();
+    if map.tag_name().name() != "map" {
+        return Err(TiledError::BadMap(String::from("root element should be <map>.")));
+    }
+    if map.attribute("infinite") == Some("1") {
+        return Err(TiledError::Unsupported(String::from("infinite maps.")));
+    }
+    let size = MapSize {
+        width: xml_u32(map, "width")?,
+        height: xml_u32(map, "height")?,
+        tile_width: xml_u32(map, "tilewidth")?,
+        tile_height: xml_u32(map, "tileheight")?
+    };
+
+    // Tilesets
+    let tilesets: Vec<_> = map.children().filter(|node| node.has_tag_name("tileset")).collect();
+    let tileset = single_tileset(tilesets.len(), tilesets.first())?;
+    if tileset.attribute("source").is_some() {
+        return Err(TiledError::Unsupported(String::from("external tilesets, embed the tileset in the map.")));
+    }
+    let first_gid = xml_u32(*tileset, "firstgid")?;
+    let mut tile_properties = HashMap::new();
+    for tile in tileset.children().filter(|node| node.has_tag_name("tile")) {
+        let mut properties = TileProperties::default();
+        for property in tile.descendants().filter(|node| node.has_tag_name("property")) {
+            let value = property.attribute("value").unwrap_or("");
+            set_property(&mut properties, property.attribute("name").unwrap_or(""), value == "true");
+        }
+        tile_properties.insert(xml_u32(tile, "id")?, properties);
+    }
+
+    // Layers
+    let mut layers = Vec::new();
+    let mut spawns = Vec::new();
+    for layer in map.children().filter(|node| node.is_element()) {
+        match layer.tag_name().name() {
+            "layer" => {
+                let name = layer.attribute("name").unwrap_or("");
+                let data = layer.children().find(|node| node.has_tag_name("data"))
+                    .ok_or_else(|| bad_map(&format!("layer \"{}\" has no data.", name)))?;
+                let gids = match data.attribute("encoding") {
+                    Some("csv") => data.text().unwrap_or("").split(',')
+                        .map(|gid| gid.trim().parse::<u32>().map_err(|_| bad_map(&format!("layer \"{}\" has a bad tile id {:?}.", name, gid.trim()))))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    None => data.children().filter(|node| node.has_tag_name("tile"))
+                        .map(|tile| tile.attribute("gid").map_or(Ok(0), |gid| gid.parse::<u32>().map_err(|_| bad_map(&format!("layer \"{}\" has a bad tile id {:?}.", name, gid)))))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    Some(encoding) => return Err(TiledError::Unsupported(format!("{} layer data, save layers as CSV.", encoding)))
+                };
+                let draw_order = layer.descendants().find(|node| node.has_tag_name("property") && node.attribute("name") == Some("draw_order"))
+                    .map(|property| property.attribute("value").and_then(|value| value.parse().ok())
+                        .ok_or_else(|| bad_map(&format!("layer \"{}\" has a draw_order that isn't a whole number.", name))))
+                    .transpose()?;
+                layers.push(tile_layer(&size, name, &gids, first_gid, draw_order)?);
+            },
+            "objectgroup" => {
+                for object in layer.children().filter(|node| node.has_tag_name("object")) {
+                    // Tiled 1.9 renamed an object's type to its class
+                    let kind = object.attribute("type").or_else(|| object.attribute("class")).unwrap_or("");
+                    let name = if kind.is_empty() { object.attribute("name").unwrap_or("") } else { kind };
+                    let x = xml_f32(object, "x")?;
+                    let mut y = xml_f32(object, "y")?;
+                    if object.attribute("gid").is_some() {
+                        y -= object.attribute("height").and_then(|height| height.parse().ok()).unwrap_or(size.tile_height as f32);
+                    }
+                    spawns.push(spawn_point(&size, name, x, y)?);
+                }
+            },
+            "group" | "imagelayer" => return Err(TiledError::Unsupported(format!("<{}> layers.", layer.tag_name().name()))),
+            _ => {}
+        }
+    }
+
+    Ok(Level {
+        width: size.width,
+        height: size.height,
+        tile_width: size.tile_width,
+        tile_height: size.tile_height,
+        layers,
+        spawns,
+        tile_properties
+    })
+}
+
+/// Parses a map saved as JSON.
+pub fn parse_json(contents: &str) -> Result<Level, TiledError> {
+    let map = json::parse(contents).map_err(|err| TiledError::Load(err.to_string()))?;
+    if !map.is_object() {
+        return Err(bad_map("map should be a JSON object."));
+    }
+    if map["infinite"].as_bool() == Some(true) {
+        return Err(TiledError::Unsupported(String::from("infinite maps.")));
+    }
+    let size = MapSize {
+        width: json_u32(&map, "width")?,
+        height: json_u32(&map, "height")?,
+        tile_width: json_u32(&map, "tilewidth")?,
+        tile_height: json_u32(&map, "tileheight")?
+    };
+
+    // Tilesets
+    let tilesets: Vec<_> = map["tilesets"].members().collect();
+    let tileset = single_tileset(tilesets.len(), tilesets.first())?;
+    if !tileset["source"].is_null() {
+        return Err(TiledError::Unsupported(String::from("external tilesets, embed the tileset in the map.")));
+    }
+    let first_gid = json_u32(tileset, "firstgid")?;
+    let mut tile_properties = HashMap::new();
+    for tile in tileset["tiles"].members() {
+        let mut properties = TileProperties::default();
+        for property in tile["properties"].members() {
+            set_property(&mut properties, property["name"].as_str().unwrap_or(""), property["value"].as_bool() == Some(true));
+        }
+        tile_properties.insert(json_u32(tile, "id")?, properties);
+    }
+
+    // Layers
+    let mut layers = Vec::new();
+    let mut spawns = Vec::new();
+    for layer in map["layers"].members() {
+        let name = layer["name"].as_str().unwrap_or("");
+        match layer["type"].as_str() {
+            Some("tilelayer") => {
+                let data = &layer["data"];
+                if data.is_string() {
+                    return Err(TiledError::Unsupported(String::from("base64 layer data, save layers as CSV.")));
+                }
+                if !data.is_array() {
+                    return Err(bad_map(&format!("layer \"{}\" has no data.", name)));
+                }
+                let gids = data.members()
+                    .map(|gid| gid.as_u32().ok_or_else(|| bad_map(&format!("layer \"{}\" has a bad tile id.", name))))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let draw_order = layer["properties"].members().find(|property| property["name"].as_str() == Some("draw_order"))
+                    .map(|property| property["value"].as_i32()
+                        .ok_or_else(|| bad_map(&format!("layer \"{}\" has a draw_order that isn't a whole number.", name))))
+                    .transpose()?;
+                layers.push(tile_layer(&size, name, &gids, first_gid, draw_order)?);
+            },
+            Some("objectgroup") => {
+                for object in layer["objects"].members() {
+                    // Tiled 1.9 renamed an object's type to its class
+                    let kind = object["type"].as_str().or_else(|| object["class"].as_str()).unwrap_or("");
+                    let name = if kind.is_empty() { object["name"].as_str().unwrap_or("") } else { kind };
+                    let x = json_f32(object, "x")?;
+                    let mut y = json_f32(object, "y")?;
+                    if !object["gid"].is_null() {
+                        y -= json_f32(object, "height").unwrap_or(size.tile_height as f32);
+                    }
+                    spawns.push(spawn_point(&size, name, x, y)?);
+                }
+            },
+            Some(kind) => return Err(TiledError::Unsupported(format!("{} layers.", kind))),
+            None => return Err(bad_map(&format!("layer \"{}\" has no type.", name)))
+        }
+    }
+
+    Ok(Level {
+        width: size.width,
+        height: size.height,
+        tile_width: size.tile_width,
+        tile_height: size.tile_height,
+        layers,
+        spawns,
+        tile_properties
+    })
+}
+
+/// Returns the only tileset.
+/// The tilemap draws from one texture, so a map can't mix tilesets.
+fn single_tileset<T>(count: usize, first: Option<T>) -> Result<T, TiledError> {
+    match (count, first) {
+        (1, Some(tileset)) => Ok(tileset),
+        (0, _) => Err(bad_map("map has no tileset.")),
+        _ => Err(TiledError::Unsupported(String::from("more than one tileset.")))
+    }
+}
+
+/// Turns global tile ids into a layer of tile indices.
+/// Flips and rotations are dropped.
+fn tile_layer(size: &MapSize, name: &str, gids: &[u32], first_gid: u32, draw_order: Option<i32>) -> Result<TileLayer, TiledError> {
+    if gids.len() != (size.width * size.height) as usize {
+        return Err(bad_map(&format!("layer \"{}\" has {} tiles, expected {}.", name, gids.len(), size.width * size.height)));
+    }
+
+    let mut tiles = Vec::with_capacity(gids.len());
+    for &gid in gids {
+        let gid = gid & !FLIP_FLAGS;
+        let tile = match gid {
+            0 => EMPTY_TILE,
+            gid if gid >= first_gid => gid - first_gid,
+            gid => return Err(bad_map(&format!("layer \"{}\" uses tile id {}, which isn't in the tileset.", name, gid)))
+        };
+        tiles.push(tile);
+    }
+
+    Ok(TileLayer {
+        name: String::from(name),
+        tiles,
+        draw_order: draw_order.or_else(|| level::default_draw_order(name))
+    })
+}
+
+/// Turns an object's pixel position into a spawn point in tiles.
+fn spawn_point(size: &MapSize, name: &str, x: f32, y: f32) -> Result<SpawnPoint, TiledError> {
+    if name.is_empty() {
+        return Err(bad_map("objects need a type or a name."));
+    }
+    let tile_x = (x / size.tile_width as f32).floor();
+    let tile_y = (y / size.tile_height as f32).floor();
+    if tile_x < 0.0 || tile_y < 0.0 || tile_x >= size.width as f32 || tile_y >= size.height as f32 {
+        return Err(bad_map(&format!("object \"{}\" is outside the map.", name)));
+    }
+
+    Ok(SpawnPoint {
+        name: String::from(name),
+        x: tile_x as u32,
+        y: tile_y as u32
+    })
+}
+
+/// Sets a known tile property.
+/// Other properties are left for the editor.
+fn set_property(properties: &mut TileProperties, name: &str, value: bool) {
+    match name {
+        "solid" => properties.solid = value,
+        "one_way" => properties.one_way = value,
+        "hazard" => properties.hazard = value,
+        _ => {}
+    }
+}
+
+/// Reads an unsigned XML attribute.
+fn xml_u32(node: roxmltree::Node, attribute: &str) -> Result<u32, TiledError> {
+    node.attribute(attribute).and_then(|value| value.parse().ok())
+        .ok_or_else(|| bad_map(&format!("<{}> needs a whole number \"{}\".", node.tag_name().name(), attribute)))
+}
+
+/// Reads a numeric XML attribute.
+fn xml_f32(node: roxmltree::Node, attribute: &str) -> Result<f32, TiledError> {
+    node.attribute(attribute).and_then(|value| value.parse().ok())
+        .ok_or_else(|| bad_map(&format!("<{}> needs a number \"{}\".", node.tag_name().name(), attribute)))
+}
+
+/// Reads an unsigned JSON field.
+fn json_u32(value: &JsonValue, field: &str) -> Result<u32, TiledError> {
+    value[field].as_u32()
+        .ok_or_else(|| bad_map(&format!("\"{}\" should be a whole number.", field)))
+}
+
+/// Reads a numeric JSON field.
+fn json_f32(value: &JsonValue, field: &str) -> Result<f32, TiledError> {
+    value[field].as_f32()
+        .ok_or_else(|| bad_map(&format!("\"{}\" should be a number.", field)))
+}
+
+/// Creates a BadMap error.
+fn bad_map(msg: &str) -> TiledError {
+    TiledError::BadMap(String::from(msg))
+}
+
+/// Test if unsupported and malformed maps are rejected.
+#[test]
+fn test_tiled_errors() {
+    let map = |tilesets: &str, layer: &str| format!(r#"{{"width": 2, "height": 1, "tilewidth": 16, "tileheight": 16, "tilesets": [{}], "layers": [{}]}}"#, tilesets, layer);
+    let tileset = r#"{"firstgid": 1}"#;
+    assert!(parse_json(&map(tileset, r#"{"type": "tilelayer", "name": "a", "data": [1, 2]}"#)).is_ok());
+    assert!(matches!(parse_json(&map("", "")), Err(TiledError::BadMap(_))));
+    assert!(matches!(parse_json(&map(&format!("{0}, {0}", tileset), "")), Err(TiledError::Unsupported(_))));
+    assert!(matches!(parse_json(&map(tileset, r#"{"type": "tilelayer", "name": "a", "data": "AAAA"}"#)), Err(TiledError::Unsupported(_))));
+    assert!(matches!(parse_json(&map(tileset, r#"{"type": "tilelayer", "name": "a", "data": [1]}"#)), Err(TiledError::BadMap(_))));
+    assert!(matches!(parse_json(&map(r#"{"firstgid": 5}"#, r#"{"type": "tilelayer", "name": "a", "data": [1, 2]}"#)), Err(TiledError::BadMap(_))));
+    assert!(matches!(parse_json(&map(tileset, r#"{"type": "tilelayer", "name": "a", "data": [1, 2.5]}"#)), Err(TiledError::BadMap(_))));
+
+    // YAML isn't JSON, even though the YAML parser would accept it
+    assert!(matches!(parse_json("width: 2"), Err(TiledError::Load(_))));
+}
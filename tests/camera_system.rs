@@ -0,0 +1,39 @@
+///
+/// Tests for the camera system, through the engine's public API.
+///
+
+use super_ferris_game::camera::Camera;
+use super_ferris_game::components::{PlayerComponent, PositionComponent};
+use super_ferris_game::entity_manager::{self, EntityManager};
+use super_ferris_game::level::LevelBounds;
+use super_ferris_game::query::Changed;
+use super_ferris_game::systems::camera_system;
+
+/// Runs the camera system, returning how many positions it changed.
+fn run_camera(entity_mgr: &mut EntityManager) -> usize {
+    let before = entity_mgr.change_tick();
+    entity_mgr.run_system(before, |entity_mgr| camera_system::update(entity_mgr));
+    entity_manager::with_last_change_tick(before, || entity_mgr.query::<Changed<&PositionComponent>>().iter().count())
+}
+
+/// Test if the player's position is only written when the camera clamps it.
+#[test]
+fn test_camera_clamps_player() {
+    let mut entity_mgr = EntityManager::new();
+    entity_mgr.insert_resource(Camera::new(256.0, 240.0));
+    entity_mgr.insert_resource(LevelBounds { width: 1000.0, height: 240.0, floor: 208.0 });
+    let player_id = entity_mgr.create_entity();
+    entity_mgr.add::<PlayerComponent>(player_id);
+    entity_mgr.add::<PositionComponent>(player_id);
+
+    // Scrolling doesn't touch the player
+    entity_mgr.get_mut::<PositionComponent>(player_id).prec_x = 300.0;
+    assert_eq!(run_camera(&mut entity_mgr), 0);
+    let camera_x = entity_mgr.res::<Camera>().x;
+    assert!(camera_x > 0.0);
+
+    // Walking off the left of the screen is stopped
+    entity_mgr.get_mut::<PositionComponent>(player_id).prec_x = camera_x - 10.0;
+    assert_eq!(run_camera(&mut entity_mgr), 1);
+    assert_eq!(entity_mgr.get::<PositionComponent>(player_id).prec_x, camera_x);
+}
@@ -0,0 +1,72 @@
+///
+/// Tests for the draw system's quad bookkeeping, which doesn't need a renderer.
+///
+
+use super_ferris_game::components::{Component, SpriteComponent};
+use super_ferris_game::entity_manager::EntityManager;
+use super_ferris_game::systems::draw_system::SpriteQuads;
+
+/// Runs the quad bookkeeping like the draw system does, handing out quad IDs in order.
+/// Returns the quads that were freed.
+fn run_quads(entity_mgr: &mut EntityManager, sprite_quads: &mut SpriteQuads, last_run: u32, next_quad: &mut u32) -> (u32, Vec<u32>) {
+    let mut freed = Vec::new();
+    let last_run = entity_mgr.run_system(last_run, |entity_mgr| {
+        freed = sprite_quads.take_removed(entity_mgr);
+        sprite_quads.assign(entity_mgr, || {
+            *next_quad += 1;
+            *next_quad - 1
+        });
+    });
+    (last_run, freed)
+}
+
+/// Test if destroying a sprite entity frees its quad.
+#[test]
+fn test_destroyed_sprite_frees_quad() {
+    let mut entity_mgr = EntityManager::new();
+    let mut sprite_quads = SpriteQuads::new();
+    let mut next_quad = 3;
+    let entity_id = entity_mgr.create_entity();
+    entity_mgr.add::<SpriteComponent>(entity_id);
+    let other_id = entity_mgr.create_entity();
+    entity_mgr.add::<SpriteComponent>(other_id);
+
+    let (last_run, _) = run_quads(&mut entity_mgr, &mut sprite_quads, 0, &mut next_quad);
+    assert_eq!(sprite_quads.get(entity_id), Some(3));
+    assert_eq!(entity_mgr.get::<SpriteComponent>(other_id).quad_id, 4);
+
+    entity_mgr.destroy_entity(entity_id);
+    let (last_run, freed) = run_quads(&mut entity_mgr, &mut sprite_quads, last_run, &mut next_quad);
+    assert_eq!(freed, vec![3]);
+    assert_eq!(sprite_quads.get(entity_id), None);
+    assert_eq!(sprite_quads.get(other_id), Some(4));
+
+    // Each quad is only freed once
+    let (_, freed) = run_quads(&mut entity_mgr, &mut sprite_quads, last_run, &mut next_quad);
+    assert!(freed.is_empty());
+}
+
+/// Test if a replaced sprite keeps its entity's quad, rather than using quad 0.
+#[test]
+fn test_replaced_sprite_keeps_quad() {
+    let mut entity_mgr = EntityManager::new();
+    let mut sprite_quads = SpriteQuads::new();
+    let mut next_quad = 0;
+    let first_id = entity_mgr.create_entity();
+    entity_mgr.add::<SpriteComponent>(first_id);
+    let entity_id = entity_mgr.create_entity();
+    entity_mgr.add::<SpriteComponent>(entity_id);
+    let (last_run, _) = run_quads(&mut entity_mgr, &mut sprite_quads, 0, &mut next_quad);
+    assert_eq!(entity_mgr.get::<SpriteComponent>(entity_id).quad_id, 1);
+
+    let mut sprite_comp = SpriteComponent::uninit();
+    sprite_comp.sprite_index = 2;
+    entity_mgr.insert(entity_id, sprite_comp);
+    assert_eq!(entity_mgr.get::<SpriteComponent>(entity_id).quad_id, 0);
+
+    let (_, freed) = run_quads(&mut entity_mgr, &mut sprite_quads, last_run, &mut next_quad);
+    assert!(freed.is_empty());
+    assert_eq!(entity_mgr.get::<SpriteComponent>(entity_id).quad_id, 1);
+    assert_eq!(entity_mgr.get::<SpriteComponent>(first_id).quad_id, 0);
+    assert_eq!(next_quad, 2);
+}
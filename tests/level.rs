@@ -0,0 +1,52 @@
+///
+/// Tests that the shipped level assets load.
+///
+
+use super_ferris_game::components::{PlayerComponent, PositionComponent, SpriteComponent};
+use super_ferris_game::entity_manager::EntityManager;
+use super_ferris_game::level::{self, Level, TERRAIN_LAYER};
+use super_ferris_game::prefab::PrefabLibrary;
+
+/// Test if the default level is well formed.
+#[test]
+fn test_default_level_loads() {
+    let level = Level::load("assets/level.txt").unwrap();
+    assert_eq!(level.width, 96);
+    assert_eq!(level.height, 15);
+
+    // Ferris stands on the two rows of ground
+    assert_eq!(level.bounds().floor, 13.0 * 16.0);
+
+    // The ground autotiles into a brick surface over rock
+    let terrain = level.layer(TERRAIN_LAYER).unwrap();
+    assert_eq!(terrain.tiles[(13 * level.width) as usize], 4);
+    assert_eq!(terrain.tiles[(14 * level.width) as usize], 0);
+}
+
+/// Test if the default level spawns its tilemaps and Ferris at the start marker.
+#[test]
+fn test_default_level_spawns() {
+    let level = Level::load("assets/level.txt").unwrap();
+    let mut entity_mgr = EntityManager::new();
+    entity_mgr.insert_resource(PrefabLibrary::load("assets/prefabs.yaml").unwrap());
+    level::spawn_level(&mut entity_mgr, &level, "tilemap").unwrap();
+
+    let tilemaps = entity_mgr.query::<&SpriteComponent>().iter().filter(|sprite_comp| sprite_comp.tilemap.is_some()).count();
+    assert_eq!(tilemaps, level.layers.iter().filter(|layer| layer.draw_order.is_some()).count());
+
+    let positions: Vec<_> = entity_mgr.query::<(&PlayerComponent, &PositionComponent)>().iter().map(|(_, pos_comp)| (pos_comp.x, pos_comp.y)).collect();
+    assert_eq!(positions, vec![(3 * 16, 12 * 16)]);
+}
+
+/// Test if a level that fails to spawn leaves no entities behind.
+#[test]
+fn test_level_spawn_failure() {
+    let level = Level::load("assets/level.txt").unwrap();
+    let mut entity_mgr = EntityManager::new();
+    entity_mgr.insert_resource(PrefabLibrary::parse("tilemap:\n  position:\n  sprite:\n").unwrap());
+
+    // The tilemaps spawn before Ferris's prefab is found missing
+    assert!(level::spawn_level(&mut entity_mgr, &level, "tilemap").is_err());
+    assert_eq!(entity_mgr.query::<&SpriteComponent>().iter().count(), 0);
+    assert_eq!(entity_mgr.query::<&PositionComponent>().iter().count(), 0);
+}
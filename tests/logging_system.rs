@@ -0,0 +1,37 @@
+///
+/// Tests for the logging system, through the engine's public API.
+///
+
+use super_ferris_game::components::LogComponent;
+use super_ferris_game::entity_manager::EntityManager;
+use super_ferris_game::systems::logging_system;
+
+/// Test if logging works on a single entity.
+#[test]
+fn test_logging_entity() {
+    let mut entity_mgr = EntityManager::new();
+    let entity_id = entity_mgr.create_entity();
+    entity_mgr.add::<LogComponent>(entity_id);
+    
+    let log_comp = entity_mgr.get_mut::<LogComponent>(entity_id);
+    log_comp.message = String::from("Logging test.");
+    log_comp.has_info = true;
+    
+    logging_system::update_entity(log_comp);
+    assert_eq!(log_comp.has_info, false);
+}
+
+/// Test if logging works across the entire entity manager.
+#[test]
+fn test_logging_entities() {
+    let mut entity_mgr = EntityManager::new();
+    let entity_id = entity_mgr.create_entity();
+    entity_mgr.add::<LogComponent>(entity_id);
+
+    let log_comp = entity_mgr.get_mut::<LogComponent>(entity_id);
+    log_comp.message = String::from("Logging test.");
+    log_comp.has_info = true;
+    
+    logging_system::update(&entity_mgr);
+    assert_eq!(entity_mgr.get::<LogComponent>(entity_id).has_info, false);
+}
\ No newline at end of file
@@ -0,0 +1,64 @@
+///
+/// Tests for the player system, through the engine's public API.
+///
+
+use super_ferris_game::components::{Component, PlayerComponent, PositionComponent};
+use super_ferris_game::entity_manager::EntityManager;
+use super_ferris_game::events::{EventReader, Events};
+use super_ferris_game::input_manager::{ButtonCode, InputManager};
+use super_ferris_game::level::LevelBounds;
+use super_ferris_game::systems::player_system::{self, PlayerEvent};
+
+const BOUNDS: LevelBounds = LevelBounds { width: 256.0, height: 240.0, floor: 208.0 };
+
+/// Test if a jump from the floor is followed by a landing.
+#[test]
+fn test_player_jump_and_land() {
+    let mut input_mgr = InputManager::new();
+    let mut pos_comp = PositionComponent::uninit();
+    pos_comp.prec_y = BOUNDS.floor - 8.0;
+
+    // Standing still sends nothing
+    assert_eq!(player_system::update_entity(&mut pos_comp, &input_mgr, &BOUNDS), None);
+
+    input_mgr.set_button_pressed(ButtonCode::A);
+    assert_eq!(player_system::update_entity(&mut pos_comp, &input_mgr, &BOUNDS), Some(PlayerEvent::Jumped));
+    assert!(pos_comp.prec_y < BOUNDS.floor - 8.0);
+    input_mgr.set_button_released(ButtonCode::A);
+
+    let mut events = Vec::new();
+    for _ in 0..120 {
+        input_mgr.update();
+        events.extend(player_system::update_entity(&mut pos_comp, &input_mgr, &BOUNDS));
+    }
+    assert_eq!(events, vec![PlayerEvent::Landed]);
+    assert_eq!(pos_comp.prec_y, BOUNDS.floor - 8.0);
+}
+
+/// Test if the player system sends its events to readers.
+#[test]
+fn test_player_events() {
+    let mut entity_mgr = EntityManager::new();
+    entity_mgr.insert_resource(InputManager::new());
+    entity_mgr.insert_resource(BOUNDS);
+    entity_mgr.add_event::<PlayerEvent>();
+    let player_id = entity_mgr.create_entity();
+    entity_mgr.add::<PlayerComponent>(player_id);
+    entity_mgr.add::<PositionComponent>(player_id);
+    entity_mgr.get_mut::<PositionComponent>(player_id).prec_y = BOUNDS.floor - 8.0;
+
+    entity_mgr.res_mut::<InputManager>().set_button_pressed(ButtonCode::A);
+    let mut event_reader = EventReader::<PlayerEvent>::new();
+    let mut events = Vec::new();
+    for _ in 0..120 {
+        player_system::update(&entity_mgr);
+        entity_mgr.apply_commands();
+        events.extend(event_reader.iter(&entity_mgr.res::<Events<PlayerEvent>>()).copied());
+        entity_mgr.update_events();
+
+        let mut input_mgr = entity_mgr.res_mut::<InputManager>();
+        input_mgr.set_button_released(ButtonCode::A);
+        input_mgr.update();
+    }
+    assert_eq!(events, vec![PlayerEvent::Jumped, PlayerEvent::Landed]);
+}
@@ -0,0 +1,78 @@
+///
+/// Tests that Tiled maps import, in both formats.
+///
+
+use super_ferris_game::components::{PositionComponent, SpriteComponent};
+use super_ferris_game::entity_manager::EntityManager;
+use super_ferris_game::level::{self, Level, SpawnPoint, EMPTY_TILE, TERRAIN_DRAW_ORDER};
+use super_ferris_game::prefab::PrefabLibrary;
+use super_ferris_game::tiled;
+
+/// Checks a map matches the tiny fixture.
+fn check_tiny(level: &Level) {
+    assert_eq!((level.width, level.height), (4, 3));
+    assert_eq!((level.tile_width, level.tile_height), (16, 16));
+
+    // Tile ids start at 1 in Tiled, and 0 is empty
+    assert_eq!(level.layers.len(), 2);
+    assert_eq!(level.layers[0].name, "terrain");
+    assert_eq!(level.layers[0].draw_order, Some(TERRAIN_DRAW_ORDER));
+    assert_eq!(level.layers[0].tiles, vec![5, 5, 5, 5, 5, 3, EMPTY_TILE, 6, 4, 4, 4, 4]);
+
+    // Flipped tiles keep their index
+    assert_eq!(level.layers[1].name, "decoration");
+    assert_eq!(level.layers[1].draw_order, Some(10));
+    assert_eq!(level.layers[1].tiles[0..2], [1, 2]);
+    assert_eq!(level.layers[1].tiles[11], 7);
+
+    // Tile objects are placed by their bottom left corner
+    assert_eq!(level.spawns, vec![
+        SpawnPoint { name: String::from("ferris"), x: 1, y: 1 },
+        SpawnPoint { name: String::from("coin"), x: 3, y: 1 }
+    ]);
+
+    assert!(level.properties(3).one_way);
+    assert!(level.properties(4).solid);
+    assert!(!level.properties(4).hazard);
+    assert!(level.properties(6).hazard);
+    assert_eq!(level.properties(5), Default::default());
+}
+
+/// Test if a TMX map imports.
+#[test]
+fn test_import_tmx() {
+    check_tiny(&Level::load("tests/fixtures/tiny.tmx").unwrap());
+}
+
+/// Test if a JSON map imports the same as its TMX version.
+#[test]
+fn test_import_json() {
+    let level = Level::load("tests/fixtures/tiny.json").unwrap();
+    check_tiny(&level);
+    assert_eq!(level, Level::load("tests/fixtures/tiny.tmx").unwrap());
+}
+
+/// Test if maps keep their own tile size, for bounds and spawning.
+#[test]
+fn test_import_tile_size() {
+    let level = tiled::parse_json(r#"{
+        "width": 4, "height": 2, "tilewidth": 32, "tileheight": 24,
+        "tilesets": [{"firstgid": 1}],
+        "layers": [
+            {"type": "tilelayer", "name": "terrain", "data": [1, 1, 1, 1, 1, 1, 1, 1]},
+            {"type": "objectgroup", "objects": [{"type": "coin", "x": 70, "y": 30}]}
+        ]
+    }"#).unwrap();
+    assert_eq!(level.spawns, vec![SpawnPoint { name: String::from("coin"), x: 2, y: 1 }]);
+    assert_eq!((level.bounds().width, level.bounds().height), (128.0, 48.0));
+
+    let mut entity_mgr = EntityManager::new();
+    entity_mgr.insert_resource(PrefabLibrary::parse("coin:\n  position:\ntilemap:\n  position:\n  sprite:\n").unwrap());
+    let entity_ids = level::spawn_level(&mut entity_mgr, &level, "tilemap").unwrap();
+
+    // Tiles are drawn on the same grid that spawns are placed on
+    let sprite_comp = entity_mgr.get::<SpriteComponent>(entity_ids[0]);
+    assert_eq!((sprite_comp.tile_width, sprite_comp.tile_height), (32, 24));
+    let pos_comp = entity_mgr.get::<PositionComponent>(entity_ids[1]);
+    assert_eq!((pos_comp.x, pos_comp.y), (64, 24));
+}